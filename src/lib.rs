@@ -0,0 +1,3 @@
+#![allow(clippy::needless_range_loop)]
+
+pub mod utils;
@@ -1,10 +1,11 @@
-mod utils;
+#![allow(clippy::needless_range_loop)]
 
-use utils::{layer::{DenseLayer, SigmoidLayer, Conv2DLayer}, model::Sequential, shape::Array};
-use utils::{dataset::MnistData};
+use rust_nn::utils::{layer::{DenseLayer, ActivationLayer, Conv2DLayer}, model::Sequential, shape::Array};
+use rust_nn::utils::ops::{Sigmoid, ReLU};
+use rust_nn::utils::{dataset::MnistData, metrics::{Accuracy, F1Score, Average}};
 use std::{time::{Instant}, fs};
 
-use crate::utils::layer::{InputLayer, ReLULayer};
+use rust_nn::utils::layer::InputLayer;
 
 fn main() {
     test_mnist();
@@ -46,7 +47,7 @@ fn test_face() {
     let mut model = Sequential::new();
     model.add(InputLayer::new(&[64, 64, 3]));
     model.add(Conv2DLayer::new(32, 3));
-    model.add(ReLULayer::new());
+    model.add(ActivationLayer::new(ReLU));
     model.compile();
     model.layers[1].set_parameters(weights, bias);
 
@@ -74,22 +75,25 @@ fn test_mnist() {
     println!("Start training...");
     let mut model = Sequential::new();
     model.add(InputLayer::new(&[1, 784]));
-    model.add(DenseLayer::new(100));
-    model.add(SigmoidLayer::new());
-    model.add(DenseLayer::new(50));
-    model.add(SigmoidLayer::new());
-    model.add(DenseLayer::new(10));
-    model.add(SigmoidLayer::new());
+    model.add(DenseLayer::with_activation(100, Sigmoid));
+    model.add(DenseLayer::with_activation(50, Sigmoid));
+    model.add(DenseLayer::with_activation(10, Sigmoid));
+
+    model.add_metric(Accuracy);
+    model.add_metric(F1Score { average: Average::Macro });
 
     model.compile();
     let timer = Instant::now();
-    model.train(&train_image_data.data[0..2048].to_vec(), &train_label_data.data[0..2048].to_vec(), 50, 1, 0.2);
+    model.train(&train_image_data.data[0..2048], &train_label_data.data[0..2048], 50, 1, 0.2, None);
     println!("Training time: {} s", timer.elapsed().as_secs());
 
     let res = model.predict(&test_image_data.data[0..4]);
-    for i in 0..res.len() {
+    for (i, r) in res.iter().enumerate() {
         println!("Case {i}:");
-        println!("guess: {:.2?}", &res[i]);
+        println!("guess: {:.2?}", r);
         println!("truth: {:.2?}", &test_label_data.data[i]);
     }
+
+    let eval = model.evaluate(&test_image_data.data[0..2048], &test_label_data.data[0..2048]);
+    println!("Test error: {:.6}, metrics: {:.4?}", eval.loss, eval.metrics);
 }
\ No newline at end of file
@@ -0,0 +1,515 @@
+use rand::rngs::StdRng;
+
+use super::{layer::{Layer, check_input_shape, pack_parameters, unpack_parameters, pack_sublayer_parameters,
+    set_sublayer_parameters, update_sublayer_parameters, pack_sublayer_deltas, DenseLayer},
+    shape::Array, initializer::Initializer, normalization::LayerNormLayer, ops::{Activation, ReLU}};
+
+/* softmax(q k^T / sqrt(d)) v for q [Tq, d], k [Tk, d] and v [Tk, dv],
+ * with `causal` query i only attends to the keys 0..=i, keys false in `key_mask` get no weight (an empty mask keeps all),
+ * returns the output [Tq, dv] and the attention weights [Tq, Tk]
+ */
+pub fn scaled_dot_product_attention(q: &Array<f64>, k: &Array<f64>, v: &Array<f64>, causal: bool, key_mask: &[bool])
+    -> (Array<f64>, Array<f64>) {
+    let mut weights = q.dot(&k.t());
+    weights.mul_v(1.0 / (q.shape[1] as f64).sqrt());
+    let cols = k.shape[0];
+    for (i, row) in weights.data.chunks_mut(cols).enumerate() {
+        let visible = if causal { (i + 1).min(cols) } else { cols };
+        let masked = |j: usize| j >= visible || key_mask.get(j) == Some(&false);
+        let max = row.iter().enumerate().filter(|&(j, _)| !masked(j)).map(|(_, &x)| x).fold(f64::MIN, f64::max);
+        let mut sum = 0.0;
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = if masked(j) { 0.0 } else { (*x - max).exp() };
+            sum += *x;
+        }
+        // a query with every key masked attends to nothing
+        if sum > 0.0 {
+            row.iter_mut().for_each(|x| *x /= sum);
+        }
+    }
+    (weights.dot(v), weights)
+}
+
+/* gradients of q, k and v from the error of scaled_dot_product_attention() with its attention weights */
+pub fn scaled_dot_product_attention_back(q: &Array<f64>, k: &Array<f64>, v: &Array<f64>, weights: &Array<f64>, error: &Array<f64>)
+    -> (Array<f64>, Array<f64>, Array<f64>) {
+    let v_error = weights.t().dot(error);
+    // softmax backward row by row, masked weights are 0 and pass no gradient
+    let mut scores_error = error.dot(&v.t());
+    let cols = k.shape[0];
+    for (e, a) in scores_error.data.chunks_mut(cols).zip(weights.data.chunks(cols)) {
+        let s: f64 = e.iter().zip(a.iter()).map(|(e, a)| e * a).sum();
+        e.iter_mut().zip(a.iter()).for_each(|(e, a)| *e = a * (*e - s));
+    }
+    scores_error.mul_v(1.0 / (q.shape[1] as f64).sqrt());
+    (scores_error.dot(k), scores_error.t().dot(q), v_error)
+}
+
+/* columns start..start + n of a 2-D array */
+fn columns(array: &Array<f64>, start: usize, n: usize) -> Array<f64> {
+    let cols = array.shape[1];
+    let data: Vec<f64> = array.data.chunks(cols).flat_map(|row| row[start..start + n].iter().copied()).collect();
+    Array::<f64>::with(&[array.shape[0], n], &data)
+}
+
+/* write `part` into the columns from start on */
+fn set_columns(array: &mut Array<f64>, start: usize, part: &Array<f64>) {
+    let (cols, n) = (array.shape[1], part.shape[1]);
+    for (row, p) in array.data.chunks_mut(cols).zip(part.data.chunks(n)) {
+        row[start..start + n].copy_from_slice(p);
+    }
+}
+
+/* x w + b with b [1, n] added to every row */
+fn affine(x: &Array<f64>, w: &Array<f64>, b: &Array<f64>) -> Array<f64> {
+    let mut out = x.dot(w);
+    let cols = b.data.len();
+    out.data.iter_mut().enumerate().for_each(|(i, o)| *o += b.data[i % cols]);
+    out
+}
+
+fn column_sums(array: &Array<f64>) -> Array<f64> {
+    let cols = array.shape[1];
+    let mut sums = Array::<f64>::zeros(&[1, cols]);
+    for row in array.data.chunks(cols) {
+        sums.data.iter_mut().zip(row.iter()).for_each(|(s, x)| *s += x);
+    }
+    sums
+}
+
+/* self-attention on [timesteps, features] with `heads` heads of size key_dim,
+ * queries, keys and values are projected to [timesteps, heads * key_dim], split into heads, attended and projected back to features,
+ * the weights wq, wk, wv, wo and the biases are packed into one [1, n] weights and one [1, n] bias array
+ */
+pub struct MultiHeadAttentionLayer {
+    pub heads: usize,
+    pub key_dim: usize,
+    pub causal: bool,
+    pub mask: Vec<bool>, // padded timesteps are not attended to, empty when unmasked
+    pub wq: Array<f64>,
+    pub wk: Array<f64>,
+    pub wv: Array<f64>,
+    pub wo: Array<f64>,
+    pub bq: Array<f64>,
+    pub bk: Array<f64>,
+    pub bv: Array<f64>,
+    pub bo: Array<f64>,
+    pub initializer: Initializer,
+    pub input: Array<f64>,
+    pub query: Array<f64>,
+    pub key: Array<f64>,
+    pub value: Array<f64>,
+    pub attention: Vec<Array<f64>>, // weights [timesteps, timesteps] of each head in the last forward pass
+    pub concat: Array<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl MultiHeadAttentionLayer {
+    pub fn new(heads: usize, key_dim: usize) -> Self {
+        MultiHeadAttentionLayer {
+            heads,
+            key_dim,
+            causal: false,
+            mask: Vec::new(),
+            wq: Array::<f64>::empty(),
+            wk: Array::<f64>::empty(),
+            wv: Array::<f64>::empty(),
+            wo: Array::<f64>::empty(),
+            bq: Array::<f64>::zeros(&[1, heads * key_dim]),
+            bk: Array::<f64>::zeros(&[1, heads * key_dim]),
+            bv: Array::<f64>::zeros(&[1, heads * key_dim]),
+            bo: Array::<f64>::empty(),
+            initializer: Initializer::GlorotUniform,
+            input: Array::<f64>::empty(),
+            query: Array::<f64>::empty(),
+            key: Array::<f64>::empty(),
+            value: Array::<f64>::empty(),
+            attention: Vec::new(),
+            concat: Array::<f64>::empty(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    /* every timestep only attends to itself and the earlier ones */
+    pub fn with_causal_mask(mut self) -> Self {
+        self.causal = true;
+        self
+    }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    fn weight_shapes(&self) -> [&[usize]; 4] {
+        [&self.wq.shape, &self.wk.shape, &self.wv.shape, &self.wo.shape]
+    }
+
+    fn bias_shapes(&self) -> [&[usize]; 4] {
+        [&self.bq.shape, &self.bk.shape, &self.bv.shape, &self.bo.shape]
+    }
+}
+
+impl Layer for MultiHeadAttentionLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("MultiHeadAttention", &self.input_shape, &input.shape);
+
+        self.query = affine(&input, &self.wq, &self.bq);
+        self.key = affine(&input, &self.wk, &self.bk);
+        self.value = affine(&input, &self.wv, &self.bv);
+        self.input = input;
+        let dk = self.key_dim;
+        self.concat = Array::<f64>::zeros(&self.query.shape);
+        self.attention.clear();
+        for h in 0..self.heads {
+            let (out, weights) = scaled_dot_product_attention(&columns(&self.query, h * dk, dk), &columns(&self.key, h * dk, dk),
+                &columns(&self.value, h * dk, dk), self.causal, &self.mask);
+            set_columns(&mut self.concat, h * dk, &out);
+            self.attention.push(weights);
+        }
+        affine(&self.concat, &self.wo, &self.bo)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let wo_error = self.concat.t().dot(&error);
+        let bo_error = column_sums(&error);
+        let concat_error = error.dot(&self.wo.t());
+
+        let dk = self.key_dim;
+        let mut query_error = Array::<f64>::zeros(&self.query.shape);
+        let mut key_error = Array::<f64>::zeros(&self.key.shape);
+        let mut value_error = Array::<f64>::zeros(&self.value.shape);
+        for h in 0..self.heads {
+            let (dq, dkey, dv) = scaled_dot_product_attention_back(&columns(&self.query, h * dk, dk), &columns(&self.key, h * dk, dk),
+                &columns(&self.value, h * dk, dk), &self.attention[h], &columns(&concat_error, h * dk, dk));
+            set_columns(&mut query_error, h * dk, &dq);
+            set_columns(&mut key_error, h * dk, &dkey);
+            set_columns(&mut value_error, h * dk, &dv);
+        }
+
+        let input_t = self.input.t();
+        let weights_error = pack_parameters(&[&input_t.dot(&query_error), &input_t.dot(&key_error), &input_t.dot(&value_error), &wo_error]);
+        let bias_error = pack_parameters(&[&column_sums(&query_error), &column_sums(&key_error), &column_sums(&value_error), &bo_error]);
+        let mut input_error = query_error.dot(&self.wq.t());
+        input_error.add_m(&key_error.dot(&self.wk.t()));
+        input_error.add_m(&value_error.dot(&self.wv.t()));
+
+        (input_error, Some(weights_error), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        let dw = unpack_parameters(delta_weights, &self.weight_shapes());
+        let db = unpack_parameters(delta_bias, &self.bias_shapes());
+        for (p, d) in [&mut self.wq, &mut self.wk, &mut self.wv, &mut self.wo].into_iter().zip(dw.iter()) {
+            p.add_m(d);
+        }
+        for (p, d) in [&mut self.bq, &mut self.bk, &mut self.bv, &mut self.bo].into_iter().zip(db.iter()) {
+            p.add_m(d);
+        }
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        let [wq, wk, wv, wo]: [Array<f64>; 4] = unpack_parameters(&weights, &self.weight_shapes()).try_into().unwrap();
+        let [bq, bk, bv, bo]: [Array<f64>; 4] = unpack_parameters(&bias, &self.bias_shapes()).try_into().unwrap();
+        (self.wq, self.wk, self.wv, self.wo) = (wq, wk, wv, wo);
+        (self.bq, self.bk, self.bv, self.bo) = (bq, bk, bv, bo);
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((pack_parameters(&[&self.wq, &self.wk, &self.wv, &self.wo]), pack_parameters(&[&self.bq, &self.bk, &self.bv, &self.bo])))
+    }
+
+    fn set_mask(&mut self, mask: Option<&[bool]>) {
+        self.mask = mask.map_or_else(Vec::new, |m| m.to_vec());
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 2 {
+            panic!("[MultiHeadAttention] input must be [timesteps, features].");
+        }
+        let (features, inner) = (prev_output_shape[1], self.heads * self.key_dim);
+        self.wq = Array::<f64>::zeros(&[features, inner]);
+        self.wk = Array::<f64>::zeros(&[features, inner]);
+        self.wv = Array::<f64>::zeros(&[features, inner]);
+        self.wo = Array::<f64>::zeros(&[inner, features]);
+        self.bo = Array::<f64>::zeros(&[1, features]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[MultiHeadAttention] config input shape: {:?}, heads: {}, key dim: {}{}", self.input_shape, self.heads, self.key_dim,
+            if self.causal { ", causal" } else { "" });
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        let (features, inner) = (self.input_shape[1], self.heads * self.key_dim);
+        for w in [&mut self.wq, &mut self.wk, &mut self.wv] {
+            *w = self.initializer.initialize(&[features, inner], features, inner, rng);
+        }
+        self.wo = self.initializer.initialize(&[inner, features], inner, features, rng);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* adds a position table to [timesteps, features],
+ * sinusoidal: sin(t / 10000^(2i / features)) in column 2i and cos in column 2i + 1, fixed,
+ * learned: the table is the weights, the bias is empty
+ */
+pub struct PositionalEncodingLayer {
+    pub learned: bool,
+    pub table: Array<f64>,
+    pub bias: Array<f64>,
+    pub initializer: Initializer,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl PositionalEncodingLayer {
+    pub fn sinusoidal() -> Self {
+        PositionalEncodingLayer {
+            learned: false,
+            table: Array::<f64>::empty(),
+            bias: Array::<f64>::empty(),
+            initializer: Initializer::Zeros,
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    pub fn learned() -> Self {
+        PositionalEncodingLayer {
+            learned: true,
+            initializer: Initializer::Uniform(-0.05, 0.05),
+            ..PositionalEncodingLayer::sinusoidal()
+        }
+    }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+}
+
+impl Layer for PositionalEncodingLayer {
+    fn forward_prop(&mut self, mut input: Array<f64>) -> Array<f64> {
+        if input.shape.len() == 2 && input.shape[0] != self.table.shape[0] {
+            panic!("[PositionalEncoding] input of {} timesteps, the table has {}.", input.shape[0], self.table.shape[0]);
+        }
+        check_input_shape("PositionalEncoding", &self.input_shape, &input.shape);
+
+        input.add_m(&self.table);
+        input
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        if !self.learned {
+            return (error, None, None);
+        }
+        let table_error = error.clone();
+        (error, Some(table_error), Some(Array::<f64>::empty()))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, _delta_bias: &Array<f64>) {
+        self.table.add_m(delta_weights);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, _bias: Array<f64>) {
+        if self.table.shape != weights.shape {
+            panic!("[PositionalEncoding] weights shape not match.");
+        }
+        self.table = weights;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        if !self.learned {
+            return None;
+        }
+        Some((self.table.clone(), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 2 {
+            panic!("[PositionalEncoding] input must be [timesteps, features].");
+        }
+        let (steps, features) = (prev_output_shape[0], prev_output_shape[1]);
+        self.table = Array::<f64>::zeros(&[steps, features]);
+        if !self.learned {
+            for t in 0..steps {
+                for i in 0..features {
+                    let angle = t as f64 / 10000f64.powf((i - i % 2) as f64 / features as f64);
+                    self.table.data[t * features + i] = if i % 2 == 0 { angle.sin() } else { angle.cos() };
+                }
+            }
+        }
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[PositionalEncoding] config shape: {:?}, {}", self.input_shape, if self.learned { "learned" } else { "sinusoidal" });
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        if self.learned {
+            let (steps, features) = (self.table.shape[0], self.table.shape[1]);
+            self.table = self.initializer.initialize(&self.table.shape, steps, features, rng);
+        }
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* post-norm Transformer encoder block on [timesteps, features]:
+ * h = LayerNorm(x + MultiHeadAttention(x)), output = LayerNorm(h + Dense(Dense(h, ff_dim, relu), features)),
+ * the parameters of the sub-layers are packed into one [1, n] weights and one [1, n] bias array
+ */
+pub struct TransformerEncoderLayer {
+    pub attention: MultiHeadAttentionLayer,
+    pub attention_norm: LayerNormLayer,
+    pub feed_forward: DenseLayer,
+    projection: Option<DenseLayer>, // back to the input features, built by config_shape
+    pub output_norm: LayerNormLayer,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl TransformerEncoderLayer {
+    pub fn new(heads: usize, key_dim: usize, ff_dim: usize) -> Self {
+        TransformerEncoderLayer {
+            attention: MultiHeadAttentionLayer::new(heads, key_dim),
+            attention_norm: LayerNormLayer::new(),
+            feed_forward: DenseLayer::with_activation(ff_dim, ReLU),
+            projection: None,
+            output_norm: LayerNormLayer::new(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    pub fn with_causal_mask(mut self) -> Self {
+        self.attention = self.attention.with_causal_mask();
+        self
+    }
+
+    /* activation of the hidden feed-forward layer, relu by default */
+    pub fn with_activation<A: Activation + 'static>(mut self, activation: A) -> Self {
+        self.feed_forward = DenseLayer::with_activation(self.feed_forward.output_shape[1], activation);
+        self
+    }
+
+    fn projection(&mut self) -> &mut DenseLayer {
+        self.projection.as_mut().expect("[TransformerEncoder] not configured.")
+    }
+
+    fn sublayers(&self) -> Vec<&dyn Layer> {
+        let mut layers: Vec<&dyn Layer> = vec![&self.attention, &self.attention_norm, &self.feed_forward];
+        if let Some(p) = &self.projection {
+            layers.push(p);
+        }
+        layers.push(&self.output_norm);
+        layers
+    }
+
+    fn sublayers_mut(&mut self) -> Vec<&mut dyn Layer> {
+        let mut layers: Vec<&mut dyn Layer> = vec![&mut self.attention, &mut self.attention_norm, &mut self.feed_forward];
+        if let Some(p) = &mut self.projection {
+            layers.push(p);
+        }
+        layers.push(&mut self.output_norm);
+        layers
+    }
+}
+
+impl Layer for TransformerEncoderLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("TransformerEncoder", &self.input_shape, &input.shape);
+
+        let mut h = self.attention.forward_prop(input.clone());
+        h.add_m(&input);
+        let h = self.attention_norm.forward_prop(h);
+        let hidden = self.feed_forward.forward_prop(h.clone());
+        let mut out = self.projection().forward_prop(hidden);
+        out.add_m(&h);
+        self.output_norm.forward_prop(out)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        // the residual connections add the error of the skipped path
+        let (out_error, w5, b5) = self.output_norm.backward_prop(error);
+        let (ff_error, w4, b4) = self.projection().backward_prop(out_error.clone());
+        let (mut h_error, w3, b3) = self.feed_forward.backward_prop(ff_error);
+        h_error.add_m(&out_error);
+        let (h_error, w2, b2) = self.attention_norm.backward_prop(h_error);
+        let (mut input_error, w1, b1) = self.attention.backward_prop(h_error.clone());
+        input_error.add_m(&h_error);
+
+        let (weights, bias) = pack_sublayer_deltas(vec![w1, w2, w3, w4, w5], vec![b1, b2, b3, b4, b5]);
+        (input_error, weights, bias)
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        update_sublayer_parameters(&mut self.sublayers_mut(), delta_weights, delta_bias);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        set_sublayer_parameters(&mut self.sublayers_mut(), weights, bias);
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        pack_sublayer_parameters(&self.sublayers())
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        self.sublayers().iter().map(|l| l.regularization_loss()).sum()
+    }
+
+    fn set_mask(&mut self, mask: Option<&[bool]>) {
+        self.attention.set_mask(mask);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        for l in self.sublayers_mut() {
+            l.init_parameters(rng);
+        }
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 2 {
+            panic!("[TransformerEncoder] input must be [timesteps, features].");
+        }
+        let mut projection = DenseLayer::new(prev_output_shape[1]);
+        let hidden = [prev_output_shape[0], self.feed_forward.output_shape[1]];
+        self.attention.config_shape(prev_output_shape);
+        self.attention_norm.config_shape(prev_output_shape);
+        self.feed_forward.config_shape(prev_output_shape);
+        projection.config_shape(&hidden);
+        self.projection = Some(projection);
+        self.output_norm.config_shape(prev_output_shape);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[TransformerEncoder] config shape: {:?}", self.input_shape);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    #[test]
+    fn multi_head_attention_gradients() {
+        check_layer(&mut MultiHeadAttentionLayer::new(2, 3), &[4, 5], true);
+        check_layer(&mut MultiHeadAttentionLayer::new(2, 3).with_causal_mask(), &[4, 5], true);
+    }
+
+    #[test]
+    fn transformer_encoder_gradients() {
+        check_layer(&mut TransformerEncoderLayer::new(2, 3, 6), &[3, 4], true);
+    }
+}
@@ -0,0 +1,378 @@
+use super::{model::Network, history::EpochRecord, shape::Array};
+
+/* hooks around training, `epoch` is 1-based and `batch` 0-based within the epoch,
+ * call model.stop_training() to end training early
+ */
+pub trait Callback {
+    fn on_train_begin(&mut self, _model: &mut dyn Network) {}
+    fn on_train_end(&mut self, _model: &mut dyn Network) {}
+    fn on_epoch_begin(&mut self, _model: &mut dyn Network, _epoch: usize) {}
+    fn on_epoch_end(&mut self, _model: &mut dyn Network, _record: &EpochRecord) {}
+    fn on_batch_begin(&mut self, _model: &mut dyn Network, _batch: usize) {}
+    fn on_batch_end(&mut self, _model: &mut dyn Network, _batch: usize, _loss: f64) {}
+    /* after on_epoch_end of every callback, once the training state (callback states included) is final for the epoch,
+     * the place to save checkpoints
+     */
+    fn on_checkpoint(&mut self, _model: &mut dyn Network, _record: &EpochRecord) {}
+    /* state saved with checkpoints, restored after on_train_begin when training resumes */
+    fn get_state(&self) -> Vec<Array<f64>> {
+        Vec::new()
+    }
+    fn set_state(&mut self, _state: Vec<Array<f64>>) {}
+}
+
+/* whether a smaller or a larger monitored value is better */
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    Min,
+    Max,
+}
+
+impl Mode {
+    fn initial(&self) -> f64 {
+        match self {
+            Mode::Min => f64::INFINITY,
+            Mode::Max => f64::NEG_INFINITY,
+        }
+    }
+
+    fn improved(&self, current: f64, best: f64, min_delta: f64) -> bool {
+        match self {
+            Mode::Min => current < best - min_delta,
+            Mode::Max => current > best + min_delta,
+        }
+    }
+}
+
+/* trainable parameters and non-trainable state of every layer */
+type Weights = (Vec<Option<(Array<f64>, Array<f64>)>>, Vec<Vec<Array<f64>>>);
+
+/* flat list of arrays: which layers have parameters, those parameters, how many state arrays each layer has,
+ * then those arrays
+ */
+fn pack_weights((weights, states): &Weights) -> Vec<Array<f64>> {
+    let present: Vec<f64> = weights.iter().map(|w| w.is_some() as usize as f64).collect();
+    let counts: Vec<f64> = states.iter().map(|s| s.len() as f64).collect();
+    let mut arrays = vec![Array::<f64>::with(&[1, present.len()], &present)];
+    weights.iter().flatten().for_each(|(w, b)| arrays.extend([w.clone(), b.clone()]));
+    arrays.push(Array::<f64>::with(&[1, counts.len()], &counts));
+    states.iter().for_each(|s| arrays.extend(s.iter().cloned()));
+    arrays
+}
+
+fn unpack_weights(arrays: Vec<Array<f64>>) -> Weights {
+    let mut arrays = arrays.into_iter();
+    let mut next = || arrays.next().expect("[Callback] truncated weights state.");
+    let present = next();
+    let weights = present.data.iter().map(|&p| if p == 1.0 { Some((next(), next())) } else { None }).collect();
+    let counts = next();
+    let states = counts.data.iter().map(|&n| (0..n as usize).map(|_| next()).collect()).collect();
+    (weights, states)
+}
+
+/* stop when the monitored value has not improved by min_delta for `patience` epochs */
+pub struct EarlyStopping {
+    pub monitor: String,
+    pub mode: Mode,
+    pub patience: usize,
+    pub min_delta: f64,
+    pub restore_best_weights: bool,
+    pub best: f64,
+    pub best_epoch: usize,
+    pub stopped_epoch: Option<usize>,
+    wait: usize,
+    best_weights: Option<Weights>,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: &str, mode: Mode, patience: usize) -> Self {
+        EarlyStopping {
+            monitor: monitor.to_string(),
+            mode,
+            patience,
+            min_delta: 0.0,
+            restore_best_weights: false,
+            best: mode.initial(),
+            best_epoch: 0,
+            stopped_epoch: None,
+            wait: 0,
+            best_weights: None,
+        }
+    }
+
+    pub fn min_delta(mut self, min_delta: f64) -> Self {
+        self.min_delta = min_delta;
+        self
+    }
+
+    /* put back the weights of the best epoch when training ends */
+    pub fn restore_best_weights(mut self) -> Self {
+        self.restore_best_weights = true;
+        self
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_train_begin(&mut self, _model: &mut dyn Network) {
+        self.best = self.mode.initial();
+        self.best_epoch = 0;
+        self.stopped_epoch = None;
+        self.wait = 0;
+        self.best_weights = None;
+    }
+
+    fn on_epoch_end(&mut self, model: &mut dyn Network, record: &EpochRecord) {
+        let current = match record.get(&self.monitor) {
+            Some(v) if !v.is_nan() => v,
+            _ => {
+                println!("[EarlyStopping] \"{}\" is not available.", self.monitor);
+                return;
+            }
+        };
+
+        if self.mode.improved(current, self.best, self.min_delta) {
+            self.best = current;
+            self.best_epoch = record.epoch;
+            self.wait = 0;
+            if self.restore_best_weights {
+                self.best_weights = Some((model.get_weights(), model.get_states()));
+            }
+        } else {
+            self.wait += 1;
+            if self.wait >= self.patience {
+                self.stopped_epoch = Some(record.epoch);
+                model.stop_training();
+                println!("[EarlyStopping] stop at epoch {}, best {}: {:.6} at epoch {}",
+                    record.epoch, self.monitor, self.best, self.best_epoch);
+            }
+        }
+    }
+
+    fn on_train_end(&mut self, model: &mut dyn Network) {
+        if let Some((weights, states)) = self.best_weights.take() {
+            println!("[EarlyStopping] restore weights of epoch {}", self.best_epoch);
+            model.set_weights(weights);
+            model.set_states(states);
+        }
+    }
+
+    /* [best, best epoch, wait, stopped epoch or -1], then the best weights if kept */
+    fn get_state(&self) -> Vec<Array<f64>> {
+        let stopped = self.stopped_epoch.map_or(-1.0, |e| e as f64);
+        let mut state = vec![Array::<f64>::with(&[1, 4], &[self.best, self.best_epoch as f64, self.wait as f64, stopped])];
+        if let Some(weights) = &self.best_weights {
+            state.extend(pack_weights(weights));
+        }
+        state
+    }
+
+    fn set_state(&mut self, mut state: Vec<Array<f64>>) {
+        assert!(!state.is_empty(), "[EarlyStopping] empty state.");
+        let weights = state.split_off(1);
+        let v = &state[0].data;
+        (self.best, self.best_epoch, self.wait) = (v[0], v[1] as usize, v[2] as usize);
+        self.stopped_epoch = if v[3] < 0.0 { None } else { Some(v[3] as usize) };
+        self.best_weights = if weights.is_empty() { None } else { Some(unpack_weights(weights)) };
+    }
+}
+
+/* save the weights every `period` epochs, or only when the monitored value improves,
+ * "{epoch}" in the file name is replaced by the epoch number,
+ * with training state the file can be passed to resume() of Sequential or the graph Model
+ */
+pub struct ModelCheckpoint {
+    pub file_name: String,
+    pub period: usize,
+    pub save_best_only: bool,
+    pub save_training_state: bool,
+    pub monitor: String,
+    pub mode: Mode,
+    pub best: f64,
+    pending: Option<String>, // file chosen at the end of the epoch, written in on_checkpoint
+}
+
+impl ModelCheckpoint {
+    pub fn new(file_name: &str, period: usize) -> Self {
+        ModelCheckpoint {
+            file_name: file_name.to_string(),
+            period: period.max(1),
+            save_best_only: false,
+            save_training_state: false,
+            monitor: "val_loss".to_string(),
+            mode: Mode::Min,
+            best: Mode::Min.initial(),
+            pending: None,
+        }
+    }
+
+    pub fn best_only(file_name: &str, monitor: &str, mode: Mode) -> Self {
+        ModelCheckpoint {
+            file_name: file_name.to_string(),
+            period: 1,
+            save_best_only: true,
+            save_training_state: false,
+            monitor: monitor.to_string(),
+            mode,
+            best: mode.initial(),
+            pending: None,
+        }
+    }
+
+    /* write a full checkpoint instead of the weights only */
+    pub fn with_training_state(mut self) -> Self {
+        self.save_training_state = true;
+        self
+    }
+}
+
+impl Callback for ModelCheckpoint {
+    fn on_train_begin(&mut self, _model: &mut dyn Network) {
+        self.best = self.mode.initial();
+        self.pending = None;
+    }
+
+    fn on_epoch_end(&mut self, _model: &mut dyn Network, record: &EpochRecord) {
+        if record.epoch % self.period != 0 {
+            return;
+        }
+
+        if self.save_best_only {
+            match record.get(&self.monitor) {
+                Some(v) if self.mode.improved(v, self.best, 0.0) => self.best = v,
+                Some(_) => return,
+                None => {
+                    println!("[ModelCheckpoint] \"{}\" is not available.", self.monitor);
+                    return;
+                }
+            }
+        }
+
+        self.pending = Some(self.file_name.replace("{epoch}", &record.epoch.to_string()));
+    }
+
+    fn on_checkpoint(&mut self, model: &mut dyn Network, _record: &EpochRecord) {
+        let file_name = match self.pending.take() {
+            Some(f) => f,
+            None => return,
+        };
+        let saved = if self.save_training_state {
+            model.save_checkpoint(&file_name)
+        } else {
+            model.save_weights(&file_name)
+        };
+        match saved {
+            Ok(()) => println!("[ModelCheckpoint] saved {}", file_name),
+            Err(e) => println!("[ModelCheckpoint] failed to save {}: {}", file_name, e),
+        }
+    }
+
+    fn get_state(&self) -> Vec<Array<f64>> {
+        vec![Array::<f64>::with(&[1, 1], &[self.best])]
+    }
+
+    fn set_state(&mut self, state: Vec<Array<f64>>) {
+        self.best = state.first().expect("[ModelCheckpoint] empty state.").data[0];
+    }
+}
+
+/* stop as soon as a batch loss is NaN or infinite */
+pub struct TerminateOnNaN;
+
+impl Callback for TerminateOnNaN {
+    fn on_batch_end(&mut self, model: &mut dyn Network, batch: usize, loss: f64) {
+        if !loss.is_finite() {
+            println!("[TerminateOnNaN] invalid loss at batch {}, stop training.", batch);
+            model.stop_training();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::utils::{model::Sequential, layer::{InputLayer, DenseLayer}};
+
+    fn model() -> Sequential {
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 2])).add(DenseLayer::new(1));
+        model.compile_with_seed(1);
+        model
+    }
+
+    fn record(epoch: usize, val_loss: f64) -> EpochRecord {
+        EpochRecord { epoch, loss: 1.0, metrics: Vec::new(), val_loss: Some(val_loss), val_metrics: Vec::new(), learning_rate: 0.1, time: 0.0 }
+    }
+
+    /* weights and bias all set to `value` */
+    fn mark(model: &mut Sequential, value: f64) {
+        let weights = model.get_weights().into_iter().map(|w| w.map(|(mut w, mut b)| {
+            w.data.iter_mut().chain(b.data.iter_mut()).for_each(|x| *x = value);
+            (w, b)
+        })).collect();
+        model.set_weights(weights);
+    }
+
+    #[test]
+    fn early_stopping_waits_patience_epochs_and_restores_the_best() {
+        let mut model = model();
+        let mut callback = EarlyStopping::new("val_loss", Mode::Min, 2).restore_best_weights();
+        callback.on_train_begin(&mut model);
+        for (epoch, val_loss) in [(1, 1.0), (2, 0.5), (3, 0.6), (4, 0.5)] {
+            mark(&mut model, epoch as f64);
+            callback.on_epoch_end(&mut model, &record(epoch, val_loss));
+            assert_eq!(model.stop_training, epoch == 4);
+        }
+        assert_eq!((callback.best, callback.best_epoch, callback.stopped_epoch), (0.5, 2, Some(4)));
+
+        callback.on_train_end(&mut model);
+        let (weights, bias) = model.get_weights()[1].clone().unwrap();
+        assert!(weights.data.iter().chain(bias.data.iter()).all(|&x| x == 2.0));
+    }
+
+    #[test]
+    fn early_stopping_state_round_trips() {
+        let mut model = model();
+        let mut callback = EarlyStopping::new("val_loss", Mode::Min, 3).restore_best_weights();
+        callback.on_train_begin(&mut model);
+        mark(&mut model, 5.0);
+        callback.on_epoch_end(&mut model, &record(1, 0.4));
+        callback.on_epoch_end(&mut model, &record(2, 0.7));
+
+        let mut restored = EarlyStopping::new("val_loss", Mode::Min, 3).restore_best_weights();
+        restored.set_state(callback.get_state());
+        assert_eq!((restored.best, restored.best_epoch, restored.wait, restored.stopped_epoch), (0.4, 1, 1, None));
+        mark(&mut model, 0.0);
+        restored.on_train_end(&mut model);
+        let (weights, _) = model.get_weights()[1].clone().unwrap();
+        assert!(weights.data.iter().all(|&x| x == 5.0));
+    }
+
+    #[test]
+    fn model_checkpoint_saves_only_improvements() {
+        let dir = env::temp_dir().join(format!("rust_nn_checkpoint_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_name = dir.join("weights_{epoch}.txt");
+
+        let mut model = model();
+        let mut callback = ModelCheckpoint::best_only(file_name.to_str().unwrap(), "val_loss", Mode::Min);
+        callback.on_train_begin(&mut model);
+        for (epoch, val_loss) in [(1, 1.0), (2, 1.2), (3, 0.8), (4, 0.8)] {
+            callback.on_epoch_end(&mut model, &record(epoch, val_loss));
+            callback.on_checkpoint(&mut model, &record(epoch, val_loss));
+        }
+        let saved: Vec<bool> = (1..=4).map(|e| dir.join(format!("weights_{}.txt", e)).exists()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(saved, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn terminate_on_nan_stops_training() {
+        let mut model = model();
+        TerminateOnNaN.on_batch_end(&mut model, 0, 0.5);
+        assert!(!model.stop_training);
+        TerminateOnNaN.on_batch_end(&mut model, 1, f64::NAN);
+        assert!(model.stop_training);
+    }
+}
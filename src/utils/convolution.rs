@@ -0,0 +1,771 @@
+use rand::rngs::StdRng;
+
+use super::{layer::{Layer, Padding, check_input_shape, pack_parameters, unpack_parameters, add_regularization, regularization_loss,
+    apply_constraints}, shape::Array, initializer::Initializer, regularizer::{Regularizer, Constraint}};
+
+/* the input position under every kernel cell for each output position, None over the padding,
+ * positions are row-major over the spatial axes, the channels are the last axis of the data,
+ * returns the spatial output size and the [outputs * kernel cells] table
+ */
+fn windows(input: &[usize], kernel_size: usize, stride: usize, padding: Padding) -> (Vec<usize>, Vec<Option<usize>>) {
+    let axes: Vec<(usize, usize)> = input.iter().map(|&size| padding.output_size(size, kernel_size, stride)).collect();
+    let output: Vec<usize> = axes.iter().map(|a| a.0).collect();
+    let kernel = vec![kernel_size; input.len()];
+    let mut taps = Vec::with_capacity(output.iter().product::<usize>() * kernel.iter().product::<usize>());
+    for p in 0..output.iter().product() {
+        let o = unravel(p, &output);
+        for c in 0..kernel.iter().product() {
+            let k = unravel(c, &kernel);
+            let mut index = Some(0);
+            for d in 0..input.len() {
+                let x = (o[d] * stride + k[d]).checked_sub(axes[d].1).filter(|&x| x < input[d]);
+                index = index.zip(x).map(|(i, x)| i * input[d] + x);
+            }
+            taps.push(index);
+        }
+    }
+    (output, taps)
+}
+
+/* row-major index --> coordinates in `dims` */
+fn unravel(mut index: usize, dims: &[usize]) -> Vec<usize> {
+    let mut coords = vec![0; dims.len()];
+    for d in (0..dims.len()).rev() {
+        coords[d] = index % dims[d];
+        index /= dims[d];
+    }
+    coords
+}
+
+/* out[p, o] = bias[o] + sum of input[tap(p, c), i] * kernel[c, i, o] over kernel cells c and input channels i */
+fn conv_forward(input: &Array<f64>, taps: &[Option<usize>], kernel: &Array<f64>, bias: Option<&Array<f64>>, output_shape: &[usize]) -> Array<f64> {
+    let dims = kernel.shape.len();
+    let (in_ch, out_ch) = (kernel.shape[dims - 2], kernel.shape[dims - 1]);
+    let cells = kernel.data.len() / in_ch / out_ch;
+    let mut output = Array::<f64>::zeros(output_shape);
+    for (p, out) in output.data.chunks_mut(out_ch).enumerate() {
+        if let Some(b) = bias {
+            out.copy_from_slice(&b.data);
+        }
+        for (c, tap) in taps[p * cells..(p + 1) * cells].iter().enumerate() {
+            let Some(t) = tap else { continue };
+            for (i, x) in input.data[t * in_ch..(t + 1) * in_ch].iter().enumerate() {
+                let w = &kernel.data[(c * in_ch + i) * out_ch..(c * in_ch + i + 1) * out_ch];
+                out.iter_mut().zip(w.iter()).for_each(|(o, w)| *o += x * w);
+            }
+        }
+    }
+    output
+}
+
+/* input error of conv_forward(), each output error spread back over its window */
+fn conv_input_error(taps: &[Option<usize>], kernel: &Array<f64>, error: &Array<f64>, input_shape: &[usize]) -> Array<f64> {
+    let dims = kernel.shape.len();
+    let (in_ch, out_ch) = (kernel.shape[dims - 2], kernel.shape[dims - 1]);
+    let cells = kernel.data.len() / in_ch / out_ch;
+    let mut input_error = Array::<f64>::zeros(input_shape);
+    for (p, e) in error.data.chunks(out_ch).enumerate() {
+        for (c, tap) in taps[p * cells..(p + 1) * cells].iter().enumerate() {
+            let Some(t) = tap else { continue };
+            for i in 0..in_ch {
+                let w = &kernel.data[(c * in_ch + i) * out_ch..(c * in_ch + i + 1) * out_ch];
+                input_error.data[t * in_ch + i] += e.iter().zip(w.iter()).map(|(e, w)| e * w).sum::<f64>();
+            }
+        }
+    }
+    input_error
+}
+
+/* kernel error of conv_forward() */
+fn conv_kernel_error(input: &Array<f64>, taps: &[Option<usize>], kernel_shape: &[usize], error: &Array<f64>) -> Array<f64> {
+    let dims = kernel_shape.len();
+    let (in_ch, out_ch) = (kernel_shape[dims - 2], kernel_shape[dims - 1]);
+    let mut kernel_error = Array::<f64>::zeros(kernel_shape);
+    let cells = kernel_error.data.len() / in_ch / out_ch;
+    for (p, e) in error.data.chunks(out_ch).enumerate() {
+        for (c, tap) in taps[p * cells..(p + 1) * cells].iter().enumerate() {
+            let Some(t) = tap else { continue };
+            for (i, x) in input.data[t * in_ch..(t + 1) * in_ch].iter().enumerate() {
+                let w = &mut kernel_error.data[(c * in_ch + i) * out_ch..(c * in_ch + i + 1) * out_ch];
+                w.iter_mut().zip(e.iter()).for_each(|(w, e)| *w += e * x);
+            }
+        }
+    }
+    kernel_error
+}
+
+/* out[p, i * m + j] = bias[i * m + j] + sum of input[tap(p, c), i] * kernel[c, i, j] over kernel cells c, m the depth multiplier */
+fn depthwise_forward(input: &Array<f64>, taps: &[Option<usize>], kernel: &Array<f64>, bias: Option<&Array<f64>>, output_shape: &[usize]) -> Array<f64> {
+    let (in_ch, mult) = (kernel.shape[2], kernel.shape[3]);
+    let cells = kernel.data.len() / in_ch / mult;
+    let mut output = Array::<f64>::zeros(output_shape);
+    for (p, out) in output.data.chunks_mut(in_ch * mult).enumerate() {
+        if let Some(b) = bias {
+            out.copy_from_slice(&b.data);
+        }
+        for (c, tap) in taps[p * cells..(p + 1) * cells].iter().enumerate() {
+            let Some(t) = tap else { continue };
+            for (i, x) in input.data[t * in_ch..(t + 1) * in_ch].iter().enumerate() {
+                let w = &kernel.data[(c * in_ch + i) * mult..(c * in_ch + i + 1) * mult];
+                out[i * mult..(i + 1) * mult].iter_mut().zip(w.iter()).for_each(|(o, w)| *o += x * w);
+            }
+        }
+    }
+    output
+}
+
+/* (input error, kernel error) of depthwise_forward() */
+fn depthwise_backward(input: &Array<f64>, taps: &[Option<usize>], kernel: &Array<f64>, error: &Array<f64>) -> (Array<f64>, Array<f64>) {
+    let (in_ch, mult) = (kernel.shape[2], kernel.shape[3]);
+    let cells = kernel.data.len() / in_ch / mult;
+    let mut input_error = Array::<f64>::zeros(&input.shape);
+    let mut kernel_error = Array::<f64>::zeros(&kernel.shape);
+    for (p, e) in error.data.chunks(in_ch * mult).enumerate() {
+        for (c, tap) in taps[p * cells..(p + 1) * cells].iter().enumerate() {
+            let Some(t) = tap else { continue };
+            for i in 0..in_ch {
+                let at = (c * in_ch + i) * mult;
+                let x = input.data[t * in_ch + i];
+                for j in 0..mult {
+                    kernel_error.data[at + j] += e[i * mult + j] * x;
+                    input_error.data[t * in_ch + i] += e[i * mult + j] * kernel.data[at + j];
+                }
+            }
+        }
+    }
+    (input_error, kernel_error)
+}
+
+/* bias error [ch, 1], the error summed over the positions */
+fn bias_backward(error: &Array<f64>, bias: &Array<f64>) -> Array<f64> {
+    let ch = bias.data.len();
+    let mut bias_error = Array::<f64>::zeros(&bias.shape);
+    for e in error.data.chunks(ch) {
+        bias_error.data.iter_mut().zip(e.iter()).for_each(|(b, e)| *b += e);
+    }
+    bias_error
+}
+
+/* fan in and fan out of a [k.., in, out] kernel */
+fn fans(kernel: &Array<f64>) -> (usize, usize) {
+    let dims = kernel.shape.len();
+    let cells: usize = kernel.shape[..dims - 2].iter().product();
+    (cells * kernel.shape[dims - 2], cells * kernel.shape[dims - 1])
+}
+
+/* the builders shared by the convolution layers */
+macro_rules! conv_builders {
+    () => {
+        pub fn with_stride(mut self, stride: usize) -> Self {
+            self.stride = stride;
+            self
+        }
+
+        pub fn with_padding(mut self, padding: Padding) -> Self {
+            self.padding = padding;
+            self
+        }
+
+        pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+            self.initializer = initializer;
+            self
+        }
+
+        pub fn with_bias_initializer(mut self, initializer: Initializer) -> Self {
+            self.bias_initializer = initializer;
+            self
+        }
+
+        pub fn with_regularizer(mut self, regularizer: Regularizer) -> Self {
+            self.regularizer = Some(regularizer);
+            self
+        }
+
+        pub fn with_bias_regularizer(mut self, regularizer: Regularizer) -> Self {
+            self.bias_regularizer = Some(regularizer);
+            self
+        }
+
+        pub fn with_constraint(mut self, constraint: Constraint) -> Self {
+            self.constraint = Some(constraint);
+            self
+        }
+
+        pub fn with_bias_constraint(mut self, constraint: Constraint) -> Self {
+            self.bias_constraint = Some(constraint);
+            self
+        }
+    };
+}
+
+/* convolution over $rank spatial axes with channels last, e.g. [steps, ch] for rank 1, [rows, cols, ch] for rank 2
+ * and [depth, rows, cols, ch] for rank 3, kernel [k, ..., in, out], bias [out, 1]
+ */
+macro_rules! new_conv_layer {
+    ($struct:ident, $name:literal, $rank:literal) => {
+        pub struct $struct {
+            pub filters: usize,
+            pub kernel_size: usize,
+            pub stride: usize,
+            pub padding: Padding,
+            pub taps: Vec<Option<usize>>,
+            pub input: Array<f64>,
+            pub weights: Array<f64>,
+            pub bias: Array<f64>,
+            pub input_shape: Box<[usize]>,
+            pub output_shape: Box<[usize]>,
+            pub initializer: Initializer,
+            pub bias_initializer: Initializer,
+            pub regularizer: Option<Regularizer>,
+            pub bias_regularizer: Option<Regularizer>,
+            pub constraint: Option<Constraint>,
+            pub bias_constraint: Option<Constraint>,
+        }
+
+        impl $struct {
+            pub fn new(filters: usize, kernel_size: usize) -> Self {
+                $struct {
+                    filters,
+                    kernel_size,
+                    stride: 1,
+                    padding: Padding::Valid,
+                    taps: Vec::new(),
+                    input: Array::<f64>::empty(),
+                    weights: Array::<f64>::empty(),
+                    bias: Array::<f64>::zeros(&[filters]),
+                    input_shape: Box::default(),
+                    output_shape: Box::default(),
+                    initializer: Initializer::GlorotUniform,
+                    bias_initializer: Initializer::Zeros,
+                    regularizer: None,
+                    bias_regularizer: None,
+                    constraint: None,
+                    bias_constraint: None,
+                }
+            }
+
+            conv_builders!();
+        }
+
+        impl Layer for $struct {
+            fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+                check_input_shape($name, &self.input_shape, &input.shape);
+
+                let output = conv_forward(&input, &self.taps, &self.weights, Some(&self.bias), &self.output_shape);
+                self.input = input;
+                output
+            }
+
+            fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+                let input_error = conv_input_error(&self.taps, &self.weights, &error, &self.input_shape);
+                let mut weights_error = conv_kernel_error(&self.input, &self.taps, &self.weights.shape, &error);
+                let mut bias_error = bias_backward(&error, &self.bias);
+                add_regularization(&mut weights_error, &self.weights, &self.regularizer);
+                add_regularization(&mut bias_error, &self.bias, &self.bias_regularizer);
+
+                (input_error, Some(weights_error), Some(bias_error))
+            }
+
+            fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+                self.weights.add_m(delta_weights);
+                self.bias.add_m(delta_bias);
+                apply_constraints(&mut self.weights, &self.constraint, &mut self.bias, &self.bias_constraint);
+            }
+
+            fn regularization_loss(&self) -> f64 {
+                regularization_loss(&self.weights, &self.regularizer, &self.bias, &self.bias_regularizer)
+            }
+
+            fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+                if self.weights.shape != weights.shape {
+                    panic!("[{}] weights shape not match.", $name);
+                }
+                if self.bias.shape != bias.shape {
+                    panic!("[{}] bias shape not match.", $name);
+                }
+                self.weights = weights;
+                self.bias = bias;
+            }
+
+            fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+                Some((self.weights.clone(), self.bias.clone()))
+            }
+
+            fn config_shape(&mut self, prev_output_shape: &[usize]) {
+                if prev_output_shape.len() != $rank + 1 {
+                    panic!("[{}] input must have {} spatial axes and the channels.", $name, $rank);
+                }
+                let in_ch = prev_output_shape[$rank];
+                let (output, taps) = windows(&prev_output_shape[..$rank], self.kernel_size, self.stride, self.padding);
+                self.taps = taps;
+                self.weights = Array::<f64>::zeros(&[vec![self.kernel_size; $rank], vec![in_ch, self.filters]].concat());
+                self.input_shape = prev_output_shape.into();
+                self.output_shape = [output, vec![self.filters]].concat().into();
+                println!("[{}] config shape:\n\tI: {:?} \n\tO: {:?} \n\tW: {:?}", $name, self.input_shape, self.output_shape, self.weights.shape);
+            }
+
+            fn init_parameters(&mut self, rng: &mut StdRng) {
+                let (fan_in, fan_out) = fans(&self.weights);
+                self.weights = self.initializer.initialize(&self.weights.shape, fan_in, fan_out, rng);
+                self.bias = self.bias_initializer.initialize(&self.bias.shape, fan_in, fan_out, rng);
+            }
+
+            fn get_output_shape(&self) -> &[usize] {
+                &self.output_shape
+            }
+        }
+    };
+}
+
+new_conv_layer!(Conv1DLayer, "Conv1D", 1);
+new_conv_layer!(Conv2DLayer, "Conv2D", 2);
+new_conv_layer!(Conv3DLayer, "Conv3D", 3);
+
+/* convolution of every channel of [rows, cols, ch] with its own depth_multiplier kernels,
+ * kernel [k, k, ch, multiplier], output channel i * multiplier + j comes from input channel i, bias [ch * multiplier, 1]
+ */
+pub struct DepthwiseConv2DLayer {
+    pub kernel_size: usize,
+    pub depth_multiplier: usize,
+    pub stride: usize,
+    pub padding: Padding,
+    pub taps: Vec<Option<usize>>,
+    pub input: Array<f64>,
+    pub weights: Array<f64>,
+    pub bias: Array<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+    pub initializer: Initializer,
+    pub bias_initializer: Initializer,
+    pub regularizer: Option<Regularizer>,
+    pub bias_regularizer: Option<Regularizer>,
+    pub constraint: Option<Constraint>,
+    pub bias_constraint: Option<Constraint>,
+}
+
+impl DepthwiseConv2DLayer {
+    pub fn new(kernel_size: usize) -> Self {
+        DepthwiseConv2DLayer {
+            kernel_size,
+            depth_multiplier: 1,
+            stride: 1,
+            padding: Padding::Valid,
+            taps: Vec::new(),
+            input: Array::<f64>::empty(),
+            weights: Array::<f64>::empty(),
+            bias: Array::<f64>::empty(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+            initializer: Initializer::GlorotUniform,
+            bias_initializer: Initializer::Zeros,
+            regularizer: None,
+            bias_regularizer: None,
+            constraint: None,
+            bias_constraint: None,
+        }
+    }
+
+    pub fn with_depth_multiplier(mut self, depth_multiplier: usize) -> Self {
+        self.depth_multiplier = depth_multiplier;
+        self
+    }
+
+    conv_builders!();
+}
+
+impl Layer for DepthwiseConv2DLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("DepthwiseConv2D", &self.input_shape, &input.shape);
+
+        let output = depthwise_forward(&input, &self.taps, &self.weights, Some(&self.bias), &self.output_shape);
+        self.input = input;
+        output
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let (input_error, mut weights_error) = depthwise_backward(&self.input, &self.taps, &self.weights, &error);
+        let mut bias_error = bias_backward(&error, &self.bias);
+        add_regularization(&mut weights_error, &self.weights, &self.regularizer);
+        add_regularization(&mut bias_error, &self.bias, &self.bias_regularizer);
+
+        (input_error, Some(weights_error), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        self.weights.add_m(delta_weights);
+        self.bias.add_m(delta_bias);
+        apply_constraints(&mut self.weights, &self.constraint, &mut self.bias, &self.bias_constraint);
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        regularization_loss(&self.weights, &self.regularizer, &self.bias, &self.bias_regularizer)
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        if self.weights.shape != weights.shape {
+            panic!("[DepthwiseConv2D] weights shape not match.");
+        }
+        if self.bias.shape != bias.shape {
+            panic!("[DepthwiseConv2D] bias shape not match.");
+        }
+        self.weights = weights;
+        self.bias = bias;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 3 {
+            panic!("[DepthwiseConv2D] input must be [rows, cols, ch].");
+        }
+        let out_ch = prev_output_shape[2] * self.depth_multiplier;
+        let (output, taps) = windows(&prev_output_shape[..2], self.kernel_size, self.stride, self.padding);
+        self.taps = taps;
+        self.weights = Array::<f64>::zeros(&[self.kernel_size, self.kernel_size, prev_output_shape[2], self.depth_multiplier]);
+        self.bias = Array::<f64>::zeros(&[out_ch]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([output[0], output[1], out_ch]);
+        println!("[DepthwiseConv2D] config shape:\n\tI: {:?} \n\tO: {:?} \n\tW: {:?}", self.input_shape, self.output_shape, self.weights.shape);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        let (fan_in, fan_out) = fans(&self.weights);
+        self.weights = self.initializer.initialize(&self.weights.shape, fan_in, fan_out, rng);
+        self.bias = self.bias_initializer.initialize(&self.bias.shape, fan_in, fan_out, rng);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* depthwise convolution without bias followed by a 1 x 1 convolution to `filters` channels,
+ * the depthwise kernel [k, k, ch, multiplier] and the pointwise kernel [1, 1, ch * multiplier, filters] are packed into the weights,
+ * the regularizer and the constraint apply to both kernels, bias [filters, 1]
+ */
+pub struct SeparableConv2DLayer {
+    pub filters: usize,
+    pub kernel_size: usize,
+    pub depth_multiplier: usize,
+    pub stride: usize,
+    pub padding: Padding,
+    pub taps: Vec<Option<usize>>,
+    pub pointwise_taps: Vec<Option<usize>>,
+    pub input: Array<f64>,
+    pub depthwise_output: Array<f64>,
+    pub weights: Array<f64>,
+    pub pointwise: Array<f64>,
+    pub bias: Array<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+    pub initializer: Initializer,
+    pub bias_initializer: Initializer,
+    pub regularizer: Option<Regularizer>,
+    pub bias_regularizer: Option<Regularizer>,
+    pub constraint: Option<Constraint>,
+    pub bias_constraint: Option<Constraint>,
+}
+
+impl SeparableConv2DLayer {
+    pub fn new(filters: usize, kernel_size: usize) -> Self {
+        SeparableConv2DLayer {
+            filters,
+            kernel_size,
+            depth_multiplier: 1,
+            stride: 1,
+            padding: Padding::Valid,
+            taps: Vec::new(),
+            pointwise_taps: Vec::new(),
+            input: Array::<f64>::empty(),
+            depthwise_output: Array::<f64>::empty(),
+            weights: Array::<f64>::empty(),
+            pointwise: Array::<f64>::empty(),
+            bias: Array::<f64>::zeros(&[filters]),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+            initializer: Initializer::GlorotUniform,
+            bias_initializer: Initializer::Zeros,
+            regularizer: None,
+            bias_regularizer: None,
+            constraint: None,
+            bias_constraint: None,
+        }
+    }
+
+    pub fn with_depth_multiplier(mut self, depth_multiplier: usize) -> Self {
+        self.depth_multiplier = depth_multiplier;
+        self
+    }
+
+    conv_builders!();
+
+    /* shape of the depthwise output */
+    fn depthwise_shape(&self) -> [usize; 3] {
+        [self.output_shape[0], self.output_shape[1], self.pointwise.shape[2]]
+    }
+}
+
+impl Layer for SeparableConv2DLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("SeparableConv2D", &self.input_shape, &input.shape);
+
+        self.depthwise_output = depthwise_forward(&input, &self.taps, &self.weights, None, &self.depthwise_shape());
+        self.input = input;
+        conv_forward(&self.depthwise_output, &self.pointwise_taps, &self.pointwise, Some(&self.bias), &self.output_shape)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let depthwise_error = conv_input_error(&self.pointwise_taps, &self.pointwise, &error, &self.depthwise_output.shape);
+        let mut pointwise_error = conv_kernel_error(&self.depthwise_output, &self.pointwise_taps, &self.pointwise.shape, &error);
+        let (input_error, mut weights_error) = depthwise_backward(&self.input, &self.taps, &self.weights, &depthwise_error);
+        let mut bias_error = bias_backward(&error, &self.bias);
+        add_regularization(&mut weights_error, &self.weights, &self.regularizer);
+        add_regularization(&mut pointwise_error, &self.pointwise, &self.regularizer);
+        add_regularization(&mut bias_error, &self.bias, &self.bias_regularizer);
+
+        (input_error, Some(pack_parameters(&[&weights_error, &pointwise_error])), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        let [depthwise, pointwise]: [Array<f64>; 2] = unpack_parameters(delta_weights, &[&self.weights.shape, &self.pointwise.shape])
+            .try_into().unwrap();
+        self.weights.add_m(&depthwise);
+        self.pointwise.add_m(&pointwise);
+        self.bias.add_m(delta_bias);
+        apply_constraints(&mut self.weights, &self.constraint, &mut self.bias, &self.bias_constraint);
+        if let Some(c) = &self.constraint {
+            c.apply(&mut self.pointwise);
+        }
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        regularization_loss(&self.weights, &self.regularizer, &self.bias, &self.bias_regularizer)
+            + self.regularizer.as_ref().map_or(0.0, |r| r.penalty(&self.pointwise))
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        if self.bias.shape != bias.shape {
+            panic!("[SeparableConv2D] bias shape not match.");
+        }
+        let [depthwise, pointwise]: [Array<f64>; 2] = unpack_parameters(&weights, &[&self.weights.shape, &self.pointwise.shape])
+            .try_into().unwrap();
+        self.weights = depthwise;
+        self.pointwise = pointwise;
+        self.bias = bias;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((pack_parameters(&[&self.weights, &self.pointwise]), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 3 {
+            panic!("[SeparableConv2D] input must be [rows, cols, ch].");
+        }
+        let mid_ch = prev_output_shape[2] * self.depth_multiplier;
+        let (output, taps) = windows(&prev_output_shape[..2], self.kernel_size, self.stride, self.padding);
+        self.taps = taps;
+        self.pointwise_taps = (0..output[0] * output[1]).map(Some).collect();
+        self.weights = Array::<f64>::zeros(&[self.kernel_size, self.kernel_size, prev_output_shape[2], self.depth_multiplier]);
+        self.pointwise = Array::<f64>::zeros(&[1, 1, mid_ch, self.filters]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([output[0], output[1], self.filters]);
+        println!("[SeparableConv2D] config shape:\n\tI: {:?} \n\tO: {:?} \n\tW: {:?} {:?}", self.input_shape, self.output_shape,
+            self.weights.shape, self.pointwise.shape);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        let (fan_in, fan_out) = fans(&self.weights);
+        self.weights = self.initializer.initialize(&self.weights.shape, fan_in, fan_out, rng);
+        let (fan_in, fan_out) = fans(&self.pointwise);
+        self.pointwise = self.initializer.initialize(&self.pointwise.shape, fan_in, fan_out, rng);
+        self.bias = self.bias_initializer.initialize(&self.bias.shape, fan_in, fan_out, rng);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* transposed convolution of [rows, cols, ch], the gradient of a Conv2D with the same kernel, stride and padding,
+ * each input cell spreads kernel * value over a window of the output, the windows stride apart,
+ * output size (size - 1) * stride + kernel for Valid and (size - 1) * stride + 1 for Same, plus the output padding at the end,
+ * the output padding is below the stride, by default 0 for Valid and stride - 1 for Same (size * stride),
+ * kernel [k, k, filters, ch] like Conv2DTranspose in Keras, bias [filters, 1]
+ */
+pub struct Conv2DTransposeLayer {
+    pub filters: usize,
+    pub kernel_size: usize,
+    pub stride: usize,
+    pub padding: Padding,
+    pub output_padding: Option<usize>,
+    pub taps: Vec<Option<usize>>,
+    pub input: Array<f64>,
+    pub weights: Array<f64>,
+    pub bias: Array<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+    pub initializer: Initializer,
+    pub bias_initializer: Initializer,
+    pub regularizer: Option<Regularizer>,
+    pub bias_regularizer: Option<Regularizer>,
+    pub constraint: Option<Constraint>,
+    pub bias_constraint: Option<Constraint>,
+}
+
+impl Conv2DTransposeLayer {
+    pub fn new(filters: usize, kernel_size: usize) -> Self {
+        Conv2DTransposeLayer {
+            filters,
+            kernel_size,
+            stride: 1,
+            padding: Padding::Valid,
+            output_padding: None,
+            taps: Vec::new(),
+            input: Array::<f64>::empty(),
+            weights: Array::<f64>::empty(),
+            bias: Array::<f64>::zeros(&[filters]),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+            initializer: Initializer::GlorotUniform,
+            bias_initializer: Initializer::Zeros,
+            regularizer: None,
+            bias_regularizer: None,
+            constraint: None,
+            bias_constraint: None,
+        }
+    }
+
+    pub fn with_output_padding(mut self, output_padding: usize) -> Self {
+        self.output_padding = Some(output_padding);
+        self
+    }
+
+    conv_builders!();
+
+    /* output size of one axis */
+    fn output_size(&self, size: usize) -> usize {
+        let (base, default) = match self.padding {
+            Padding::Valid => ((size - 1) * self.stride + self.kernel_size, 0),
+            Padding::Same => ((size - 1) * self.stride + 1, self.stride - 1),
+        };
+        let output_padding = self.output_padding.unwrap_or(default);
+        if output_padding >= self.stride {
+            panic!("[Conv2DTranspose] output padding {} must be below the stride {}.", output_padding, self.stride);
+        }
+        base + output_padding
+    }
+}
+
+impl Layer for Conv2DTransposeLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Conv2DTranspose", &self.input_shape, &input.shape);
+
+        let mut output = conv_input_error(&self.taps, &self.weights, &input, &self.output_shape);
+        for out in output.data.chunks_mut(self.filters) {
+            out.iter_mut().zip(self.bias.data.iter()).for_each(|(o, b)| *o += b);
+        }
+        self.input = input;
+        output
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let input_error = conv_forward(&error, &self.taps, &self.weights, None, &self.input_shape);
+        let mut weights_error = conv_kernel_error(&error, &self.taps, &self.weights.shape, &self.input);
+        let mut bias_error = bias_backward(&error, &self.bias);
+        add_regularization(&mut weights_error, &self.weights, &self.regularizer);
+        add_regularization(&mut bias_error, &self.bias, &self.bias_regularizer);
+
+        (input_error, Some(weights_error), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        self.weights.add_m(delta_weights);
+        self.bias.add_m(delta_bias);
+        apply_constraints(&mut self.weights, &self.constraint, &mut self.bias, &self.bias_constraint);
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        regularization_loss(&self.weights, &self.regularizer, &self.bias, &self.bias_regularizer)
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        if self.weights.shape != weights.shape {
+            panic!("[Conv2DTranspose] weights shape not match.");
+        }
+        if self.bias.shape != bias.shape {
+            panic!("[Conv2DTranspose] bias shape not match.");
+        }
+        self.weights = weights;
+        self.bias = bias;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 3 {
+            panic!("[Conv2DTranspose] input must be [rows, cols, ch].");
+        }
+        let output = [self.output_size(prev_output_shape[0]), self.output_size(prev_output_shape[1])];
+        // the windows of the convolution from the output back to the input
+        let (input, taps) = windows(&output, self.kernel_size, self.stride, self.padding);
+        debug_assert_eq!(input, prev_output_shape[..2]);
+        self.taps = taps;
+        self.weights = Array::<f64>::zeros(&[self.kernel_size, self.kernel_size, self.filters, prev_output_shape[2]]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([output[0], output[1], self.filters]);
+        println!("[Conv2DTranspose] config shape:\n\tI: {:?} \n\tO: {:?} \n\tW: {:?}", self.input_shape, self.output_shape, self.weights.shape);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        // fans of the [k, k, filters, ch] kernel seen from the input side
+        let (fan_out, fan_in) = fans(&self.weights);
+        self.weights = self.initializer.initialize(&self.weights.shape, fan_in, fan_out, rng);
+        self.bias = self.bias_initializer.initialize(&self.bias.shape, fan_in, fan_out, rng);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    #[test]
+    fn conv1d_gradients() {
+        check_layer(&mut Conv1DLayer::new(3, 3).with_stride(2).with_padding(Padding::Same), &[7, 2], true);
+    }
+
+    #[test]
+    fn conv2d_gradients() {
+        check_layer(&mut Conv2DLayer::new(2, 3).with_padding(Padding::Same), &[5, 4, 2], true);
+    }
+
+    #[test]
+    fn conv3d_gradients() {
+        check_layer(&mut Conv3DLayer::new(2, 2).with_stride(2), &[4, 3, 4, 2], true);
+    }
+
+    #[test]
+    fn depthwise_conv2d_gradients() {
+        check_layer(&mut DepthwiseConv2DLayer::new(3).with_depth_multiplier(2).with_padding(Padding::Same), &[5, 5, 2], true);
+    }
+
+    #[test]
+    fn separable_conv2d_gradients() {
+        check_layer(&mut SeparableConv2DLayer::new(3, 3).with_depth_multiplier(2).with_stride(2), &[6, 5, 2], true);
+    }
+
+    #[test]
+    fn conv2d_transpose_gradients() {
+        check_layer(&mut Conv2DTransposeLayer::new(2, 3).with_stride(2).with_output_padding(1), &[3, 3, 2], true);
+        check_layer(&mut Conv2DTransposeLayer::new(2, 3).with_stride(2).with_padding(Padding::Same), &[3, 2, 2], true);
+    }
+}
@@ -0,0 +1,77 @@
+use super::{dataset::Dataset, model::{Sequential, Evaluation}, random::stream_rng};
+
+pub struct CrossValidation {
+    pub folds: Vec<Evaluation>,
+    pub mean: Evaluation,
+}
+
+/* k-fold cross-validation, `seed` fixes the fold assignment
+ * `build` must return a fresh compiled model, it is called with the fold index
+ */
+pub fn cross_validate<F>(mut build: F, data: &Dataset, k: usize, epoches: usize, batch_size: usize, learning_rate: f64, seed: u64) -> CrossValidation
+where
+    F: FnMut(usize) -> Sequential,
+{
+    let mut folds: Vec<Evaluation> = Vec::with_capacity(k);
+
+    for (f, (train, val)) in data.k_fold(k, &mut stream_rng(seed, 0)).iter().enumerate() {
+        let mut model = build(f);
+        model.train(&train.inputs, &train.truths, epoches, batch_size, learning_rate, None);
+
+        let eval = model.evaluate(&val.inputs, &val.truths);
+        print!("fold {}/{}, error: {:.6}", f + 1, k, eval.loss);
+        for (name, value) in eval.metrics.iter() {
+            print!(", {}: {:.4}", name, value);
+        }
+        println!();
+        folds.push(eval);
+    }
+
+    let mean = Evaluation {
+        loss: folds.iter().map(|e| e.loss).sum::<f64>() / k as f64,
+        metrics: folds[0].metrics.iter().enumerate().map(|(m, (name, _))| {
+            (name.clone(), folds.iter().map(|e| e.metrics[m].1).sum::<f64>() / k as f64)
+        }).collect(),
+    };
+    print!("cross validation mean error: {:.6}", mean.loss);
+    for (name, value) in mean.metrics.iter() {
+        print!(", {}: {:.4}", name, value);
+    }
+    println!();
+    CrossValidation { folds, mean }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{layer::{InputLayer, DenseLayer}, metrics::MeanAbsoluteError};
+
+    #[test]
+    fn every_fold_is_evaluated_and_the_seed_fixes_them() {
+        let inputs: Vec<Vec<f64>> = (0..12).map(|i| vec![i as f64 / 12.0]).collect();
+        let truths: Vec<Vec<f64>> = inputs.iter().map(|x| vec![2.0 * x[0] - 0.5]).collect();
+        let data = Dataset::new(&inputs, &truths);
+        let run = |seed| {
+            let mut built = Vec::new();
+            let cv = cross_validate(|f| {
+                built.push(f);
+                let mut model = Sequential::new();
+                model.add(InputLayer::new(&[1, 1])).add(DenseLayer::new(1)).add_metric(MeanAbsoluteError);
+                model.compile_with_seed(5);
+                model
+            }, &data, 4, 3, 2, 0.1, seed);
+            (built, cv)
+        };
+
+        let (built, cv) = run(9);
+        assert_eq!(built, vec![0, 1, 2, 3]);
+        assert_eq!(cv.folds.len(), 4);
+        let mean = cv.folds.iter().map(|e| e.loss).sum::<f64>() / 4.0;
+        assert!((cv.mean.loss - mean).abs() < 1e-12);
+        assert_eq!(cv.mean.metrics[0].0, "mae");
+
+        let losses = |cv: &CrossValidation| cv.folds.iter().map(|e| e.loss).collect::<Vec<f64>>();
+        assert_eq!(losses(&cv), losses(&run(9).1));
+        assert_ne!(losses(&cv), losses(&run(10).1));
+    }
+}
@@ -1,4 +1,7 @@
 use std::{fs::{File, self}, io::Read};
+use rand::{seq::SliceRandom, Rng};
+
+use super::shape::Array;
 
 pub struct MnistData {
     pub sizes: Vec<i32>,
@@ -11,7 +14,7 @@ impl MnistData {
 
         let mut buffer = vec![0; metadata.len() as usize];
         let mut file = File::open(file_name).expect("Unable to open file.");
-        file.read(&mut buffer).expect("Buffer overflow.");
+        file.read_exact(&mut buffer).expect("Buffer overflow.");
 
         let magic_number = i32::from_be_bytes(buffer[0..4].try_into().unwrap());
 
@@ -22,7 +25,7 @@ impl MnistData {
             2049 => {
                 sizes.push(i32::from_be_bytes(buffer[4..8].try_into().unwrap()));
                 data = buffer[8..].to_vec().iter().map(|&x| {
-                    let mut v = vec![0.0 as f64; 10]; v[x as usize] = 1.0;
+                    let mut v = vec![0.0_f64; 10]; v[x as usize] = 1.0;
                     v
                 }).collect();
                 Ok(MnistData { sizes, data })
@@ -43,4 +46,180 @@ impl MnistData {
             _ => panic!(),
         }
     }
-}
\ No newline at end of file
+}
+
+/* a set of samples with their ground truth */
+#[derive(Clone, Default)]
+pub struct Dataset {
+    pub inputs: Vec<Vec<f64>>,
+    pub truths: Vec<Vec<f64>>,
+}
+
+impl Dataset {
+    pub fn new(inputs: &[Vec<f64>], truths: &[Vec<f64>]) -> Self {
+        assert!(inputs.len() == truths.len(), "[Dataset] inputs and truths have different lengths.");
+        Dataset { inputs: inputs.to_vec(), truths: truths.to_vec() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /* collect the samples at the given indices */
+    pub fn select(&self, indices: &[usize]) -> Dataset {
+        Dataset {
+            inputs: indices.iter().map(|&i| self.inputs[i].clone()).collect(),
+            truths: indices.iter().map(|&i| self.truths[i].clone()).collect(),
+        }
+    }
+
+    /* randomly split into (train, validation), the validation part holding `ratio` of the samples */
+    pub fn split<R: Rng + ?Sized>(&self, ratio: f64, rng: &mut R) -> (Dataset, Dataset) {
+        assert!((0.0..=1.0).contains(&ratio), "[Dataset] split ratio must be in [0, 1].");
+        let indices = Array::<f64>::permutation(self.len(), rng);
+
+        let val_len = (self.len() as f64 * ratio).round() as usize;
+        (self.select(&indices[val_len..]), self.select(&indices[..val_len]))
+    }
+
+    /* split into (train, validation) keeping the class proportions of the one-hot truths in both parts */
+    pub fn stratified_split<R: Rng + ?Sized>(&self, ratio: f64, rng: &mut R) -> (Dataset, Dataset) {
+        assert!((0.0..=1.0).contains(&ratio), "[Dataset] split ratio must be in [0, 1].");
+        let mut train_indices: Vec<usize> = Vec::new();
+        let mut val_indices: Vec<usize> = Vec::new();
+
+        for mut class in self.class_indices() {
+            class.shuffle(rng);
+            let val_len = (class.len() as f64 * ratio).round() as usize;
+            val_indices.extend_from_slice(&class[..val_len]);
+            train_indices.extend_from_slice(&class[val_len..]);
+        }
+
+        train_indices.shuffle(rng);
+        val_indices.shuffle(rng);
+        (self.select(&train_indices), self.select(&val_indices))
+    }
+
+    /* shuffle the samples and cut them into k (train, validation) folds */
+    pub fn k_fold<R: Rng + ?Sized>(&self, k: usize, rng: &mut R) -> Vec<(Dataset, Dataset)> {
+        assert!(k > 1 && k <= self.len(), "[Dataset] k must be in [2, number of samples].");
+        let indices = Array::<f64>::permutation(self.len(), rng);
+
+        let mut folds = Vec::with_capacity(k);
+        for f in 0..k {
+            // fold sizes differ by at most one sample
+            let start = f * self.len() / k;
+            let end = (f + 1) * self.len() / k;
+            let train = [&indices[..start], &indices[end..]].concat();
+            folds.push((self.select(&train), self.select(&indices[start..end])));
+        }
+        folds
+    }
+
+    /* copy with gaussian noise of the given std added to every input, for augmentation */
+    pub fn with_gaussian_noise<R: Rng + ?Sized>(&self, std: f64, rng: &mut R) -> Dataset {
+        let inputs = self.inputs.iter().map(|x| {
+            let noise = Array::<f64>::normal_with(&[x.len()], 0.0, std, rng);
+            x.iter().zip(noise.data.iter()).map(|(x, n)| x + n).collect()
+        }).collect();
+        Dataset { inputs, truths: self.truths.clone() }
+    }
+
+    /* sample indices grouped by the arg max of their truth */
+    fn class_indices(&self) -> Vec<Vec<usize>> {
+        let classes = self.truths.first().map_or(0, |t| t.len());
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); classes.max(1)];
+        for (i, t) in self.truths.iter().enumerate() {
+            groups[arg_max(t)].push(i);
+        }
+        groups
+    }
+}
+
+pub fn arg_max(v: &[f64]) -> usize {
+    let mut index = 0;
+    for i in 1..v.len() {
+        if v[i] > v[index] {
+            index = i;
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /* sample i has input [i] and one of 3 classes, 10 of class 0, 20 of class 1 and 30 of class 2 */
+    fn data() -> Dataset {
+        let inputs: Vec<Vec<f64>> = (0..60).map(|i| vec![i as f64]).collect();
+        let truths: Vec<Vec<f64>> = (0..60).map(|i| {
+            let mut t = vec![0.0; 3];
+            t[if i < 10 { 0 } else if i < 30 { 1 } else { 2 }] = 1.0;
+            t
+        }).collect();
+        Dataset::new(&inputs, &truths)
+    }
+
+    fn ids(d: &Dataset) -> Vec<usize> {
+        let mut ids: Vec<usize> = d.inputs.iter().map(|x| x[0] as usize).collect();
+        ids.sort();
+        ids
+    }
+
+    fn class_counts(d: &Dataset) -> Vec<usize> {
+        let mut counts = vec![0; 3];
+        d.truths.iter().for_each(|t| counts[arg_max(t)] += 1);
+        counts
+    }
+
+    #[test]
+    fn split_sizes() {
+        let (train, val) = data().split(0.25, &mut StdRng::seed_from_u64(1));
+        assert_eq!((train.len(), val.len()), (45, 15));
+        let mut all = [ids(&train), ids(&val)].concat();
+        all.sort();
+        assert_eq!(all, (0..60).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stratified_split_keeps_class_balance() {
+        let (train, val) = data().stratified_split(0.2, &mut StdRng::seed_from_u64(1));
+        assert_eq!(class_counts(&val), vec![2, 4, 6]);
+        assert_eq!(class_counts(&train), vec![8, 16, 24]);
+    }
+
+    #[test]
+    fn k_fold_is_disjoint_and_covers_every_sample() {
+        let data = data();
+        let folds = data.k_fold(7, &mut StdRng::seed_from_u64(1));
+        assert_eq!(folds.len(), 7);
+        let mut all_val: Vec<usize> = Vec::new();
+        for (train, val) in folds.iter() {
+            assert!(val.len() == 8 || val.len() == 9);
+            assert_eq!(train.len() + val.len(), 60);
+            let mut both = [ids(train), ids(val)].concat();
+            both.sort();
+            assert_eq!(both, (0..60).collect::<Vec<_>>());
+            all_val.extend(ids(val));
+        }
+        all_val.sort();
+        assert_eq!(all_val, (0..60).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn same_seed_same_folds() {
+        let data = data();
+        let folds = |seed| -> Vec<Vec<usize>> {
+            data.k_fold(5, &mut StdRng::seed_from_u64(seed)).iter().map(|(_, val)| ids(val)).collect()
+        };
+        assert_eq!(folds(3), folds(3));
+        assert_ne!(folds(3), folds(4));
+    }
+}
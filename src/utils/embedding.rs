@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use rand::rngs::StdRng;
+
+use super::{layer::{Layer, check_input_shape}, shape::Array, initializer::Initializer};
+
+/* lookup table from integer indices [1, timesteps] (stored as f64) to vectors [timesteps, dim],
+ * the weights are [vocab, dim] and the bias is empty,
+ * backward_prop gathers the gradient of the looked up rows only and apply_sparse_gradients updates just those,
+ * the optional padding index maps to a zero vector and its row is never trained,
+ * it also masks its timesteps for the following layers: recurrent layers carry their state over them,
+ * attention gives them no weight, layers keeping the timesteps pass the mask on
+ */
+pub struct EmbeddingLayer {
+    pub vocab: usize,
+    pub dim: usize,
+    pub padding_index: Option<usize>,
+    pub indices: Vec<usize>,
+    pub row_gradients: BTreeMap<usize, Vec<f64>>, // summed gradient of the rows looked up since the last update
+    pub weights: Array<f64>,
+    pub bias: Array<f64>,
+    pub initializer: Initializer,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl EmbeddingLayer {
+    pub fn new(vocab: usize, dim: usize) -> Self {
+        EmbeddingLayer {
+            vocab,
+            dim,
+            padding_index: None,
+            indices: Vec::new(),
+            row_gradients: BTreeMap::new(),
+            weights: Array::<f64>::zeros(&[vocab, dim]),
+            bias: Array::<f64>::empty(),
+            initializer: Initializer::Uniform(-0.05, 0.05),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    pub fn with_padding_index(mut self, index: usize) -> Self {
+        self.padding_index = Some(index);
+        self
+    }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    fn index(&self, value: f64) -> usize {
+        if value < 0.0 || value.fract() != 0.0 || value >= self.vocab as f64 {
+            panic!("[Embedding] {} is not an index below {}.", value, self.vocab);
+        }
+        value as usize
+    }
+
+    fn zero_padding_row(&mut self) {
+        if let Some(p) = self.padding_index {
+            self.weights.data[p * self.dim..(p + 1) * self.dim].iter_mut().for_each(|w| *w = 0.0);
+        }
+    }
+}
+
+impl Layer for EmbeddingLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Embedding", &self.input_shape, &input.shape);
+
+        self.indices = input.data.iter().map(|&v| self.index(v)).collect();
+        let dim = self.dim;
+        let mut data = Vec::with_capacity(self.indices.len() * dim);
+        for &i in self.indices.iter() {
+            if Some(i) == self.padding_index {
+                data.extend(std::iter::repeat_n(0.0, dim));
+            } else {
+                data.extend_from_slice(&self.weights.data[i * dim..(i + 1) * dim]);
+            }
+        }
+        Array::<f64>::with(&[self.indices.len(), dim], &data)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let dim = self.dim;
+        for (t, &i) in self.indices.iter().enumerate() {
+            if Some(i) == self.padding_index {
+                continue;
+            }
+            let row = self.row_gradients.entry(i).or_insert_with(|| vec![0.0; dim]);
+            for (g, e) in row.iter_mut().zip(error.data[t * dim..(t + 1) * dim].iter()) {
+                *g += e;
+            }
+        }
+        // indices are not differentiable, the row gradients are applied by apply_sparse_gradients
+        (Array::<f64>::zeros(&self.input_shape), None, None)
+    }
+
+    fn apply_sparse_gradients(&mut self, scale: f64) {
+        let dim = self.dim;
+        for (i, row) in std::mem::take(&mut self.row_gradients) {
+            for (w, g) in self.weights.data[i * dim..(i + 1) * dim].iter_mut().zip(row.iter()) {
+                *w += scale * g;
+            }
+        }
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, _bias: Array<f64>) {
+        if self.weights.shape != weights.shape {
+            panic!("[Embedding] weights shape not match.");
+        }
+        self.weights = weights;
+        self.zero_padding_row();
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 2 || prev_output_shape[0] != 1 {
+            panic!("[Embedding] input must be [1, timesteps].");
+        }
+        if self.padding_index.is_some_and(|p| p >= self.vocab) {
+            panic!("[Embedding] padding index beyond the vocabulary.");
+        }
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([prev_output_shape[1], self.dim]);
+        println!("[Embedding] config input shape: {:?}, output shape: {:?}, vocabulary: {}", self.input_shape, self.output_shape, self.vocab);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        self.weights = self.initializer.initialize(&self.weights.shape, self.vocab, self.dim, rng);
+        self.zero_padding_row();
+    }
+
+    fn output_mask(&self, _mask: Option<Vec<bool>>) -> Option<Vec<bool>> {
+        self.padding_index.map(|p| self.indices.iter().map(|&i| i != p).collect())
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::utils::{model::Sequential, layer::{InputLayer, DropoutLayer}, recurrent::{LSTMLayer, GRULayer, BidirectionalLayer},
+        attention::MultiHeadAttentionLayer, gradient_check::check_layer};
+
+    /* embedding, a layer passing the mask on and `last`, with the same weights for every sequence length */
+    fn masked_model<L: Layer + 'static>(timesteps: usize, last: L) -> Sequential {
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, timesteps]))
+            .add(EmbeddingLayer::new(6, 3).with_padding_index(0))
+            .add(DropoutLayer::new(0.5))
+            .add(last);
+        model.compile_with_seed(11);
+        model
+    }
+
+    #[test]
+    fn padded_timesteps_are_skipped_by_recurrent_layers() {
+        let padded = masked_model(5, LSTMLayer::new(4)).predict(&[vec![2.0, 5.0, 0.0, 1.0, 0.0]]);
+        let unpadded = masked_model(3, LSTMLayer::new(4)).predict(&[vec![2.0, 5.0, 1.0]]);
+        assert_eq!(padded, unpadded);
+
+        // the state is carried over a padded timestep and is its output
+        let sequence = masked_model(3, GRULayer::new(2).with_return_sequences()).predict(&[vec![4.0, 0.0, 3.0]]);
+        assert_eq!(sequence[0][0..2], sequence[0][2..4]);
+    }
+
+    #[test]
+    fn padded_timesteps_get_no_attention() {
+        let padded = masked_model(4, MultiHeadAttentionLayer::new(2, 2)).predict(&[vec![3.0, 1.0, 0.0, 0.0]]);
+        let unpadded = masked_model(2, MultiHeadAttentionLayer::new(2, 2)).predict(&[vec![3.0, 1.0]]);
+        for (p, u) in padded[0][..6].iter().zip(unpadded[0].iter()) {
+            assert!((p - u).abs() < 1e-12, "{} vs {}", p, u);
+        }
+    }
+
+    #[test]
+    fn masked_gradients() {
+        let mask = [true, false, true, false];
+        let mut lstm = LSTMLayer::new(3).with_return_sequences();
+        lstm.set_mask(Some(&mask));
+        check_layer(&mut lstm, &[4, 2], true);
+        let mut bidirectional = BidirectionalLayer::new(GRULayer::new(2).with_return_sequences(), GRULayer::new(2).with_return_sequences());
+        bidirectional.set_mask(Some(&mask));
+        check_layer(&mut bidirectional, &[4, 2], true);
+        let mut attention = MultiHeadAttentionLayer::new(2, 2);
+        attention.set_mask(Some(&mask));
+        check_layer(&mut attention, &[4, 3], true);
+    }
+
+    #[test]
+    fn sparse_gradients_update_looked_up_rows() {
+        let mut layer = EmbeddingLayer::new(5, 2).with_padding_index(0);
+        layer.config_shape(&[1, 4]);
+        layer.init_parameters(&mut StdRng::seed_from_u64(7));
+        let before = layer.weights.clone();
+
+        layer.forward_prop(Array::<f64>::with(&[1, 4], &[3.0, 0.0, 1.0, 3.0]));
+        let (input_error, delta_weights, _) = layer.backward_prop(Array::<f64>::with(&[4, 2], &[1.0, 2.0, 5.0, 5.0, 3.0, 4.0, 0.5, 0.5]));
+        assert!(delta_weights.is_none());
+        assert!(input_error.data.iter().all(|&e| e == 0.0));
+        layer.apply_sparse_gradients(-1.0);
+
+        let change: Vec<f64> = layer.weights.data.iter().zip(before.data.iter()).map(|(a, b)| a - b).collect();
+        // row 3 sums timesteps 0 and 3, the padding row 0 and the unused rows 2 and 4 do not move
+        assert_eq!(change, vec![0.0, 0.0, -3.0, -4.0, 0.0, 0.0, -1.5, -2.5, 0.0, 0.0]);
+        assert!(layer.row_gradients.is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{layer::Layer, merge::MergeLayer, shape::Array};
+
+const EPSILON: f64 = 1e-5;
+const TOLERANCE: f64 = 1e-6;
+
+/* config `layer` for `input_shape` and compare the gradients backward_prop gives for
+ * loss = sum(output * probe), with a random probe, against central differences,
+ * the input gradient is skipped when `check_input` is false (e.g. integer inputs)
+ */
+pub fn check_layer(layer: &mut dyn Layer, input_shape: &[usize], check_input: bool) {
+    let mut rng = StdRng::seed_from_u64(7);
+    layer.config_shape(input_shape);
+    layer.init_parameters(&mut rng);
+    // zero initialized biases would hide errors in their gradient
+    if let Some((weights, bias)) = layer.get_parameters().filter(|(_, b)| !b.data.is_empty()) {
+        let bias = Array::<f64>::random_with(&bias.shape, -0.5, 0.5, &mut rng);
+        layer.set_parameters(weights, bias);
+    }
+    layer.set_training(true);
+
+    let input = Array::<f64>::random_with(input_shape, -1.0, 1.0, &mut rng);
+    let probe = Array::<f64>::random_with(layer.get_output_shape(), -1.0, 1.0, &mut rng);
+    layer.forward_prop(input.clone());
+    let (input_error, delta_weights, delta_bias) = layer.backward_prop(probe.clone());
+
+    let loss = |layer: &mut dyn Layer, x: &Array<f64>| -> f64 {
+        layer.forward_prop(x.clone()).data.iter().zip(probe.data.iter()).map(|(o, p)| o * p).sum()
+    };
+
+    if check_input {
+        for i in 0..input.data.len() {
+            let numeric = central_difference(&input, i, |x| loss(layer, x));
+            assert_close("input", i, input_error.data[i], numeric);
+        }
+    }
+
+    if let Some((weights, bias)) = layer.get_parameters() {
+        let (delta_weights, delta_bias) = (delta_weights.unwrap(), delta_bias.unwrap());
+        for i in 0..weights.data.len() {
+            let numeric = central_difference(&weights, i, |w| {
+                layer.set_parameters(w.clone(), bias.clone());
+                loss(layer, &input)
+            });
+            assert_close("weights", i, delta_weights.data[i], numeric);
+        }
+        for i in 0..bias.data.len() {
+            let numeric = central_difference(&bias, i, |b| {
+                layer.set_parameters(weights.clone(), b.clone());
+                loss(layer, &input)
+            });
+            assert_close("bias", i, delta_bias.data[i], numeric);
+        }
+        layer.set_parameters(weights, bias);
+    }
+}
+
+/* same check for a merge layer, on the error of every input */
+pub fn check_merge_layer(layer: &mut dyn MergeLayer, input_shapes: &[&[usize]]) {
+    let mut rng = StdRng::seed_from_u64(7);
+    layer.config_shape(input_shapes);
+
+    let inputs: Vec<Array<f64>> = input_shapes.iter().map(|s| Array::<f64>::random_with(s, -1.0, 1.0, &mut rng)).collect();
+    let probe = Array::<f64>::random_with(layer.get_output_shape(), -1.0, 1.0, &mut rng);
+    layer.forward_prop(inputs.clone());
+    let errors = layer.backward_prop(probe.clone());
+
+    for (n, input) in inputs.iter().enumerate() {
+        for i in 0..input.data.len() {
+            let numeric = central_difference(input, i, |x| {
+                let mut shifted = inputs.clone();
+                shifted[n] = x.clone();
+                layer.forward_prop(shifted).data.iter().zip(probe.data.iter()).map(|(o, p)| o * p).sum()
+            });
+            assert_close("input", i, errors[n].data[i], numeric);
+        }
+    }
+}
+
+/* derivative of f along element i of x */
+fn central_difference<F: FnMut(&Array<f64>) -> f64>(x: &Array<f64>, i: usize, mut f: F) -> f64 {
+    let mut shifted = x.clone();
+    shifted.data[i] = x.data[i] + EPSILON;
+    let plus = f(&shifted);
+    shifted.data[i] = x.data[i] - EPSILON;
+    let minus = f(&shifted);
+    (plus - minus) / (2.0 * EPSILON)
+}
+
+fn assert_close(what: &str, i: usize, analytic: f64, numeric: f64) {
+    let scale = analytic.abs().max(numeric.abs()).max(1.0);
+    assert!((analytic - numeric).abs() <= TOLERANCE * scale,
+        "{} gradient {}: backward_prop {} vs numeric {}", what, i, analytic, numeric);
+}
@@ -0,0 +1,547 @@
+use std::io;
+
+use crate::utils::{loss::{MSE, Loss}, shape::Array};
+use super::{layer::{Layer, InputLayer}, merge::{MergeLayer, AddLayer}, metrics::Metric, history::History, callback::Callback,
+    model::{Network, Trainer, TrainState, Evaluation, fit}, random::stream_rng};
+
+/* index of a node in a graph Model */
+pub type NodeId = usize;
+
+/* one vector per input (or output) node of a Model */
+pub type Sample = Vec<Vec<f64>>;
+
+pub enum Operation {
+    Layer(Box<dyn Layer>),       // one input, none for the input layers of the model
+    Merge(Box<dyn MergeLayer>),  // two or more inputs
+}
+
+pub struct Node {
+    pub operation: Operation,
+    pub inputs: Vec<NodeId>,
+}
+
+impl Node {
+    fn get_output_shape(&self) -> &[usize] {
+        match &self.operation {
+            Operation::Layer(l) => l.get_output_shape(),
+            Operation::Merge(m) => m.get_output_shape(),
+        }
+    }
+
+    fn layer(&self) -> Option<&dyn Layer> {
+        match &self.operation {
+            Operation::Layer(l) => Some(l.as_ref()),
+            Operation::Merge(_) => None,
+        }
+    }
+
+    fn layer_mut(&mut self) -> Option<&mut Box<dyn Layer>> {
+        match &mut self.operation {
+            Operation::Layer(l) => Some(l),
+            Operation::Merge(_) => None,
+        }
+    }
+}
+
+/* model on a graph of layers, e.g. a residual block:
+ *     let x = model.input(&[1, 16]);
+ *     let h = model.layer(DenseLayer::with_activation(16, ReLU), x);
+ *     let y = model.merge(AddLayer::new(), &[x, h]);
+ *     model.set_outputs(&[y]);
+ * nodes only take earlier nodes as inputs, so the graph has no cycles,
+ * a sample is one vector per input node and its truth one vector per output node,
+ * training shares the loop of Sequential, with the same metrics, callbacks and checkpoints
+ */
+#[derive(Default)]
+pub struct Model {
+    pub nodes: Vec<Node>,
+    pub inputs: Vec<NodeId>,
+    pub outputs: Vec<NodeId>,
+    pub order: Vec<NodeId>, // topological order of the nodes the outputs depend on, set by compile
+    pub metrics: Vec<Box<dyn Metric>>,
+    pub callbacks: Vec<Box<dyn Callback>>,
+    pub stop_training: bool, // set by callbacks to end training after the current batch
+    pub state: TrainState,
+    pub loss_weights: Vec<f64>, // weight of the loss of each output, all 1.0 if empty
+    pub seed: u64,
+    pub shuffle: bool,
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Model::default()
+    }
+
+    /* new input of the model, samples give the inputs in the order they are added */
+    pub fn input(&mut self, shape: &[usize]) -> NodeId {
+        let id = self.push(Operation::Layer(Box::new(InputLayer::new(shape))), Vec::new());
+        self.inputs.push(id);
+        id
+    }
+
+    /* layer applied to the output of `input` */
+    pub fn layer<L>(&mut self, layer: L, input: NodeId) -> NodeId
+    where
+        L: Layer + 'static,
+    {
+        self.push(Operation::Layer(Box::new(layer)), vec![input])
+    }
+
+    /* merge layer joining the outputs of `inputs` */
+    pub fn merge<M>(&mut self, layer: M, inputs: &[NodeId]) -> NodeId
+    where
+        M: MergeLayer + 'static,
+    {
+        self.push(Operation::Merge(Box::new(layer)), inputs.to_vec())
+    }
+
+    /* `layers` chained on `input`, with their output added to `input` */
+    pub fn residual_block(&mut self, layers: Vec<Box<dyn Layer>>, input: NodeId) -> NodeId {
+        let mut x = input;
+        for layer in layers {
+            x = self.push(Operation::Layer(layer), vec![x]);
+        }
+        self.merge(AddLayer::new(), &[input, x])
+    }
+
+    /* outputs of the model, predictions and truths follow this order */
+    pub fn set_outputs(&mut self, outputs: &[NodeId]) -> &mut Self {
+        assert!(outputs.iter().all(|&o| o < self.nodes.len()), "[Model] unknown output node.");
+        self.outputs = outputs.to_vec();
+        self
+    }
+
+    /* register a metric computed on each output, named "<metric>_<output>" when the model has several outputs */
+    pub fn add_metric<M>(&mut self, metric: M) -> &mut Self
+    where
+        M: Metric + 'static,
+    {
+        self.metrics.push(Box::new(metric));
+        self
+    }
+
+    /* register a callback invoked around training, epochs and batches */
+    pub fn add_callback<C>(&mut self, callback: C) -> &mut Self
+    where
+        C: Callback + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /* the loss is the sum over the outputs of weight * MSE, one weight per output */
+    pub fn set_loss_weights(&mut self, weights: &[f64]) -> &mut Self {
+        assert!(weights.iter().all(|w| *w >= 0.0), "[Model] loss weights must not be negative.");
+        self.loss_weights = weights.to_vec();
+        self
+    }
+
+    /* shuffle the training samples every epoch */
+    pub fn set_shuffle(&mut self, shuffle: bool) -> &mut Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    fn push(&mut self, operation: Operation, inputs: Vec<NodeId>) -> NodeId {
+        assert!(inputs.iter().all(|&i| i < self.nodes.len()), "[Model] input node does not exist yet.");
+        self.nodes.push(Node { operation, inputs });
+        self.nodes.len() - 1
+    }
+
+    /* post-order from the outputs, every node comes after its inputs */
+    fn topological_order(&self) -> Vec<NodeId> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        for &output in self.outputs.iter() {
+            // explicit stack of (node, inputs pushed), deep graphs would overflow a recursion
+            let mut stack = vec![(output, false)];
+            while let Some((id, expanded)) = stack.pop() {
+                if expanded {
+                    order.push(id);
+                } else if !visited[id] {
+                    visited[id] = true;
+                    stack.push((id, true));
+                    stack.extend(self.nodes[id].inputs.iter().rev().filter(|&&i| !visited[i]).map(|&i| (i, false)));
+                }
+            }
+        }
+        order
+    }
+
+    /* config the shape of each node from its inputs, with a random seed */
+    pub fn compile(&mut self) {
+        self.compile_with_seed(rand::random());
+    }
+
+    /* config the shape of each node from its inputs and initialize the parameters,
+     * the same seed gives the same weights and the same training run
+     */
+    pub fn compile_with_seed(&mut self, seed: u64) {
+        assert!(!self.outputs.is_empty(), "[Model] no outputs, call set_outputs first.");
+        assert!(self.loss_weights.is_empty() || self.loss_weights.len() == self.outputs.len(),
+            "[Model] {} loss weights for {} outputs.", self.loss_weights.len(), self.outputs.len());
+        self.order = self.topological_order();
+        if let Some(i) = self.inputs.iter().find(|i| !self.order.contains(i)) {
+            panic!("[Model] input node {} does not reach any output.", i);
+        }
+
+        self.seed = seed;
+        let mut rng = stream_rng(seed, 0);
+        for &id in self.order.iter() {
+            let shapes: Vec<Vec<usize>> = self.nodes[id].inputs.iter().map(|&i| self.nodes[i].get_output_shape().to_vec()).collect();
+            match &mut self.nodes[id].operation {
+                Operation::Layer(l) => {
+                    if let Some(shape) = shapes.first() {
+                        l.config_shape(shape);
+                    }
+                    if l.batch_statistics() {
+                        panic!("[Model] node {} normalizes over the batch, graph models train sample by sample.", id);
+                    }
+                    l.init_parameters(&mut rng);
+                }
+                Operation::Merge(m) => {
+                    let shapes: Vec<&[usize]> = shapes.iter().map(|s| s.as_slice()).collect();
+                    m.config_shape(&shapes);
+                }
+            }
+        }
+    }
+
+    fn layers(&self) -> impl Iterator<Item = &dyn Layer> {
+        self.nodes.iter().filter_map(|n| n.layer())
+    }
+
+    fn layers_mut(&mut self) -> impl Iterator<Item = &mut Box<dyn Layer>> {
+        self.nodes.iter_mut().filter_map(|n| n.layer_mut())
+    }
+
+    /* summed weight penalties of all layers */
+    pub fn regularization_loss(&self) -> f64 {
+        self.layers().map(|l| l.regularization_loss()).sum()
+    }
+
+    /* switch every layer between training and inference behavior */
+    pub fn set_training(&mut self, training: bool) {
+        self.layers_mut().for_each(|l| l.set_training(training));
+    }
+
+    /* run one sample through the nodes in topological order, an output read by several nodes is cloned for each,
+     * a layer gets the padding mask of its input node, merges drop the masks
+     */
+    fn forward(&mut self, input: &[Vec<f64>]) -> Vec<Array<f64>> {
+        assert!(input.len() == self.inputs.len(), "[Model] sample has {} inputs, model has {}.", input.len(), self.inputs.len());
+
+        let mut outputs: Vec<Option<Array<f64>>> = vec![None; self.nodes.len()];
+        let mut masks: Vec<Option<Vec<bool>>> = vec![None; self.nodes.len()];
+        for (&id, data) in self.inputs.iter().zip(input.iter()) {
+            outputs[id] = Some(Array::<f64>::with(self.nodes[id].get_output_shape(), data));
+        }
+        for &id in self.order.iter() {
+            let node = &mut self.nodes[id];
+            let output = match &mut node.operation {
+                Operation::Layer(l) => {
+                    let (x, mask) = match node.inputs.first() {
+                        Some(&i) => (outputs[i].clone().unwrap(), masks[i].clone()),
+                        None => (outputs[id].take().unwrap(), None),
+                    };
+                    l.set_mask(mask.as_deref());
+                    let output = l.forward_prop(x);
+                    masks[id] = l.output_mask(mask);
+                    output
+                }
+                Operation::Merge(m) => m.forward_prop(node.inputs.iter().map(|&i| outputs[i].clone().unwrap()).collect()),
+            };
+            outputs[id] = Some(output);
+        }
+        self.outputs.iter().map(|&o| outputs[o].clone().unwrap()).collect()
+    }
+
+    /* backward the errors of the outputs in reverse topological order, the errors of a node read by
+     * several nodes are summed before it is backwarded, returns the deltas of each node
+     */
+    fn backward(&mut self, output_errors: Vec<Array<f64>>) -> Vec<Option<(Array<f64>, Array<f64>)>> {
+        let mut errors: Vec<Option<Array<f64>>> = vec![None; self.nodes.len()];
+        for (&id, error) in self.outputs.iter().zip(output_errors) {
+            accumulate(&mut errors[id], error);
+        }
+
+        let mut deltas: Vec<Option<(Array<f64>, Array<f64>)>> = vec![None; self.nodes.len()];
+        for &id in self.order.iter().rev() {
+            let error = match errors[id].take() {
+                Some(e) => e,
+                None => continue,
+            };
+            let node = &mut self.nodes[id];
+            match &mut node.operation {
+                Operation::Layer(l) => {
+                    let (back_output, delta_weights, delta_bias) = l.backward_prop(error);
+                    if let (Some(w), Some(b)) = (delta_weights, delta_bias) {
+                        deltas[id] = Some((w, b));
+                    }
+                    if let Some(&i) = node.inputs.first() {
+                        accumulate(&mut errors[i], back_output);
+                    }
+                }
+                Operation::Merge(m) => {
+                    for (&i, e) in node.inputs.iter().zip(m.backward_prop(error)) {
+                        accumulate(&mut errors[i], e);
+                    }
+                }
+            }
+        }
+        deltas
+    }
+
+    /* make prediction, one vector per output for each sample */
+    pub fn predict(&mut self, input: &[Sample]) -> Vec<Sample> {
+        self.set_training(false);
+        input.iter().map(|sample| self.forward(sample).into_iter().map(|o| o.into_vec()).collect()).collect()
+    }
+
+    /* weighted loss summed over the outputs, averaged over the samples, weights are left untouched */
+    pub fn evaluate(&mut self, input: &[Sample], truth: &[Sample]) -> Evaluation {
+        assert!(!input.is_empty() && truth.len() == input.len());
+
+        self.set_training(false);
+        let mut loss = 0.0;
+        let mut predict: Vec<Sample> = Vec::with_capacity(input.len());
+        for (sample, t) in input.iter().zip(truth.iter()) {
+            let (l, outputs, _) = self.sample_loss(sample, t);
+            loss += l;
+            predict.push(outputs.into_iter().map(|o| o.into_vec()).collect());
+        }
+        loss = loss / input.len() as f64 + self.regularization_loss();
+
+        let metrics = self.metric_values(truth, &predict);
+        Evaluation { loss, metrics }
+    }
+
+    fn loss_weight(&self, output: usize) -> f64 {
+        self.loss_weights.get(output).copied().unwrap_or(1.0)
+    }
+
+    /* forward one sample, its weighted loss, the outputs and the weighted loss gradient of every output */
+    fn sample_loss(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>]) -> (f64, Vec<Array<f64>>, Vec<Array<f64>>) {
+        assert!(truth.len() == self.outputs.len(), "[Model] sample has {} truths, model has {} outputs.", truth.len(), self.outputs.len());
+
+        let outputs = self.forward(input);
+        let mut loss = 0.0;
+        let mut errors = Vec::with_capacity(outputs.len());
+        for (o, (output, t)) in outputs.iter().zip(truth.iter()).enumerate() {
+            let weight = self.loss_weight(o);
+            loss += weight * MSE::calculate(t, output);
+            let mut error = MSE::derivative(t, output.clone());
+            error.mul_v(weight);
+            errors.push(error);
+        }
+        (loss, outputs, errors)
+    }
+
+    /* forward and backward the samples at `batch`, apply the summed gradients and return the summed error,
+     * the outputs are pushed to `predictions` when the model has metrics
+     */
+    fn train_batch(&mut self, input: &[Sample], truth: &[Sample], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Sample>) -> f64 {
+        let mut err = 0.0;
+        let mut batch_deltas: Vec<Option<(Array<f64>, Array<f64>)>> = vec![None; self.nodes.len()];
+        for &index in batch.iter() {
+            let (loss, outputs, errors) = self.sample_loss(&input[index], &truth[index]);
+            err += loss;
+            if !self.metrics.is_empty() {
+                predictions.push(outputs.into_iter().map(|o| o.into_vec()).collect());
+            }
+
+            for (sum, delta) in batch_deltas.iter_mut().zip(self.backward(errors)) {
+                match (sum.as_mut(), delta) {
+                    (Some((w, b)), Some((dw, db))) => {
+                        w.add_m(&dw);
+                        b.add_m(&db);
+                    }
+                    (None, delta) => *sum = delta,
+                    (Some(_), None) => {}
+                }
+            }
+        }
+
+        for (node, delta) in self.nodes.iter_mut().zip(batch_deltas) {
+            if let (Some(l), Some((mut w, mut b))) = (node.layer_mut(), delta) {
+                l.update_parameters(w.mul_v(-learning_rate), b.mul_v(-learning_rate));
+            }
+        }
+        self.layers_mut().for_each(|l| l.apply_sparse_gradients(-learning_rate));
+        err
+    }
+
+    /* train with mini-batch gradient descent, `validation` is evaluated after every epoch */
+    pub fn train(&mut self, input: &[Sample], truth: &[Sample], epoches: usize, batch_size: usize, learning_rate: f64,
+        validation: Option<(&[Sample], &[Sample])>) -> History {
+        self.state = TrainState { epoches, batch_size, learning_rate, seed: self.seed, ..TrainState::default() };
+        fit(self, input, truth, validation)
+    }
+
+    /* continue a run from a checkpoint written by save_checkpoint(), on the same data, see Sequential::resume() */
+    pub fn resume(&mut self, file_name: &str, input: &[Sample], truth: &[Sample], validation: Option<(&[Sample], &[Sample])>)
+        -> Result<History, io::Error> {
+        self.load_checkpoint(file_name)?;
+        self.seed = self.state.seed;
+        println!("[Model] resume from epoch {}/{}, batch {}, step {}", self.state.epoch, self.state.epoches, self.state.batch, self.state.step);
+        Ok(fit(self, input, truth, validation))
+    }
+}
+
+impl Network for Model {
+    /* trainable parameters of every node, None for merge nodes and layers without parameters */
+    fn get_weights(&self) -> Vec<Option<(Array<f64>, Array<f64>)>> {
+        self.nodes.iter().map(|n| n.layer().and_then(|l| l.get_parameters())).collect()
+    }
+
+    fn set_weights(&mut self, weights: Vec<Option<(Array<f64>, Array<f64>)>>) {
+        assert!(weights.len() == self.nodes.len(), "[Model] weights do not match the nodes.");
+        for (n, w) in self.nodes.iter_mut().zip(weights) {
+            if let (Some(l), Some((weights, bias))) = (n.layer_mut(), w) {
+                l.set_parameters(weights, bias);
+            }
+        }
+    }
+
+    /* non-trainable state of every node, empty for merge nodes and stateless layers */
+    fn get_states(&self) -> Vec<Vec<Array<f64>>> {
+        self.nodes.iter().map(|n| n.layer().map_or_else(Vec::new, |l| l.get_state())).collect()
+    }
+
+    fn set_states(&mut self, states: Vec<Vec<Array<f64>>>) {
+        assert!(states.len() == self.nodes.len(), "[Model] states do not match the nodes.");
+        for (n, s) in self.nodes.iter_mut().zip(states) {
+            if let (Some(l), false) = (n.layer_mut(), s.is_empty()) {
+                l.set_state(s);
+            }
+        }
+    }
+
+    fn train_state(&self) -> &TrainState {
+        &self.state
+    }
+
+    fn train_state_mut(&mut self) -> &mut TrainState {
+        &mut self.state
+    }
+
+    fn stop_training(&mut self) {
+        self.stop_training = true;
+    }
+}
+
+impl Trainer for Model {
+    type Sample = Sample;
+
+    fn layers_mut(&mut self) -> Vec<&mut (dyn Layer + 'static)> {
+        self.nodes.iter_mut().filter_map(|n| n.layer_mut()).map(|l| l.as_mut()).collect()
+    }
+
+    fn callbacks_mut(&mut self) -> &mut Vec<Box<dyn Callback>> {
+        &mut self.callbacks
+    }
+
+    fn stop_requested(&mut self) -> &mut bool {
+        &mut self.stop_training
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    fn truncation(&self) -> Option<usize> {
+        None
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        Model::regularization_loss(self)
+    }
+
+    fn train_batch(&mut self, input: &[Sample], truth: &[Sample], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Sample>) -> f64 {
+        Model::train_batch(self, input, truth, batch, learning_rate, predictions)
+    }
+
+    fn evaluate(&mut self, input: &[Sample], truth: &[Sample]) -> Evaluation {
+        Model::evaluate(self, input, truth)
+    }
+
+    /* every metric on every output, suffixed with the output index when there are several */
+    fn metric_values(&self, truth: &[Sample], predict: &[Sample]) -> Vec<(String, f64)> {
+        let mut values = Vec::with_capacity(self.metrics.len() * self.outputs.len());
+        for o in 0..self.outputs.len() {
+            let t: Vec<Vec<f64>> = truth.iter().map(|s| s[o].clone()).collect();
+            let p: Vec<Vec<f64>> = predict.iter().map(|s| s[o].clone()).collect();
+            for m in self.metrics.iter() {
+                let name = if self.outputs.len() > 1 { format!("{}_{}", m.name(), o) } else { m.name() };
+                values.push((name, m.calculate(&t, &p)));
+            }
+        }
+        values
+    }
+}
+
+/* add `error` to the error gathered so far at a node */
+fn accumulate(sum: &mut Option<Array<f64>>, error: Array<f64>) {
+    match sum {
+        Some(s) => { s.add_m(&error); }
+        None => *sum = Some(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{layer::DenseLayer, merge::ConcatenateLayer, normalization::LayerNormLayer, ops::TanH};
+
+    fn residual_model(seed: u64) -> Model {
+        let mut model = Model::new();
+        let x = model.input(&[1, 3]);
+        let h = model.layer(DenseLayer::with_activation(4, TanH), x);
+        let r = model.residual_block(vec![Box::new(DenseLayer::with_activation(4, TanH)), Box::new(LayerNormLayer::new())], h);
+        let y = model.layer(DenseLayer::new(2), r);
+        let c = model.merge(ConcatenateLayer::new(), &[x, r]);
+        let z = model.layer(DenseLayer::new(1), c);
+        model.set_outputs(&[y, z]).set_loss_weights(&[1.0, 0.5]).set_shuffle(true);
+        model.compile_with_seed(seed);
+        model
+    }
+
+    fn data() -> (Vec<Sample>, Vec<Sample>) {
+        let input: Vec<Sample> = (0..12).map(|i| vec![vec![(i as f64 * 0.7).sin(), (i as f64 * 0.3).cos(), i as f64 / 12.0]]).collect();
+        let truth: Vec<Sample> = input.iter().map(|s| vec![vec![s[0][0] * s[0][1], s[0][2]], vec![s[0][0] - s[0][2]]]).collect();
+        (input, truth)
+    }
+
+    #[test]
+    fn same_seed_same_training_run() {
+        let (input, truth) = data();
+        // losses of every epoch and all parameters, compared bit for bit
+        let runs: Vec<(Vec<f64>, Vec<f64>)> = (0..2).map(|_| {
+            let mut model = residual_model(42);
+            let history = model.train(&input, &truth, 4, 5, 0.05, Some((&input[..4], &truth[..4])));
+            let parameters = model.get_weights().into_iter().flatten().flat_map(|(w, b)| [w.into_vec(), b.into_vec()].concat()).collect();
+            (history.epochs.iter().map(|r| r.loss).collect(), parameters)
+        }).collect();
+
+        assert_eq!(runs[0], runs[1]);
+
+        let mut other = residual_model(43);
+        let history = other.train(&input, &truth, 4, 5, 0.05, None);
+        assert_ne!(runs[0].0, history.epochs.iter().map(|r| r.loss).collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn training_reduces_the_weighted_loss() {
+        let (input, truth) = data();
+        let mut model = residual_model(7);
+        let before = model.evaluate(&input, &truth).loss;
+        model.train(&input, &truth, 30, 4, 0.05, None);
+        assert!(model.evaluate(&input, &truth).loss < before);
+    }
+}
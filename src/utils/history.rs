@@ -0,0 +1,190 @@
+use std::{fs, io};
+
+/* statistics of one training epoch */
+#[derive(Clone, Debug)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    pub loss: f64,
+    pub metrics: Vec<(String, f64)>, // on the training outputs of the epoch, made while the weights changed
+    pub val_loss: Option<f64>,
+    pub val_metrics: Vec<(String, f64)>,
+    pub learning_rate: f64,
+    pub time: f64, // wall time of the training part of the epoch in seconds, validation excluded
+}
+
+impl EpochRecord {
+    /* look up "loss", "val_loss", "<metric>" or "val_<metric>" */
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match name {
+            "loss" => Some(self.loss),
+            "val_loss" => self.val_loss,
+            _ => match name.strip_prefix("val_") {
+                Some(m) => find_metric(&self.val_metrics, m),
+                None => find_metric(&self.metrics, name),
+            },
+        }
+    }
+}
+
+/* per-epoch record of a training run, returned by Sequential::train */
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    pub epochs: Vec<EpochRecord>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn push(&mut self, record: EpochRecord) {
+        self.epochs.push(record);
+    }
+
+    pub fn len(&self) -> usize {
+        self.epochs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.epochs.is_empty()
+    }
+
+    pub fn loss(&self) -> Vec<f64> {
+        self.epochs.iter().map(|e| e.loss).collect()
+    }
+
+    pub fn val_loss(&self) -> Vec<Option<f64>> {
+        self.epochs.iter().map(|e| e.val_loss).collect()
+    }
+
+    /* values of a training metric, None for epochs where it was not computed */
+    pub fn metric(&self, name: &str) -> Vec<Option<f64>> {
+        self.epochs.iter().map(|e| find_metric(&e.metrics, name)).collect()
+    }
+
+    /* values of a validation metric, None for epochs where it was not computed */
+    pub fn val_metric(&self, name: &str) -> Vec<Option<f64>> {
+        self.epochs.iter().map(|e| find_metric(&e.val_metrics, name)).collect()
+    }
+
+    /* training and validation metric names in the order they were first recorded */
+    fn metric_names(&self) -> (Vec<String>, Vec<String>) {
+        (self.names_of(|e| &e.metrics), self.names_of(|e| &e.val_metrics))
+    }
+
+    fn names_of(&self, metrics: fn(&EpochRecord) -> &Vec<(String, f64)>) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for e in self.epochs.iter() {
+            for (n, _) in metrics(e).iter() {
+                if !names.contains(n) {
+                    names.push(n.clone());
+                }
+            }
+        }
+        names
+    }
+
+    /* one row per epoch, missing values are left empty */
+    pub fn to_csv(&self) -> String {
+        let (names, val_names) = self.metric_names();
+        let mut header = vec!["epoch".to_string(), "loss".to_string()];
+        header.extend(names.iter().cloned());
+        header.push("val_loss".to_string());
+        header.extend(val_names.iter().map(|n| format!("val_{}", n)));
+        header.push("learning_rate".to_string());
+        header.push("time".to_string());
+
+        let mut lines = vec![header.join(",")];
+        for e in self.epochs.iter() {
+            let mut row = vec![e.epoch.to_string(), e.loss.to_string()];
+            for n in names.iter() {
+                row.push(find_metric(&e.metrics, n).map_or(String::new(), |v| v.to_string()));
+            }
+            row.push(e.val_loss.map_or(String::new(), |v| v.to_string()));
+            for n in val_names.iter() {
+                row.push(find_metric(&e.val_metrics, n).map_or(String::new(), |v| v.to_string()));
+            }
+            row.push(e.learning_rate.to_string());
+            row.push(e.time.to_string());
+            lines.push(row.join(","));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /* array of per-epoch objects, missing and non-finite values are null */
+    pub fn to_json(&self) -> String {
+        let mut records: Vec<String> = Vec::new();
+        for e in self.epochs.iter() {
+            let mut fields = vec![
+                format!("\"epoch\":{}", e.epoch),
+                format!("\"loss\":{}", json_number(Some(e.loss))),
+            ];
+            for (n, v) in e.metrics.iter() {
+                fields.push(format!("\"{}\":{}", json_escape(n), json_number(Some(*v))));
+            }
+            fields.push(format!("\"val_loss\":{}", json_number(e.val_loss)));
+            for (n, v) in e.val_metrics.iter() {
+                fields.push(format!("\"val_{}\":{}", json_escape(n), json_number(Some(*v))));
+            }
+            fields.push(format!("\"learning_rate\":{}", json_number(Some(e.learning_rate))));
+            fields.push(format!("\"time\":{}", json_number(Some(e.time))));
+            records.push(format!("  {{{}}}", fields.join(",")));
+        }
+        format!("[\n{}\n]\n", records.join(",\n"))
+    }
+
+    pub fn save_csv(&self, file_name: &str) -> Result<(), io::Error> {
+        fs::write(file_name, self.to_csv())
+    }
+
+    pub fn save_json(&self, file_name: &str) -> Result<(), io::Error> {
+        fs::write(file_name, self.to_json())
+    }
+}
+
+fn find_metric(metrics: &[(String, f64)], name: &str) -> Option<f64> {
+    metrics.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+}
+
+fn json_number(v: Option<f64>) -> String {
+    match v {
+        Some(x) if x.is_finite() => x.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{model::Sequential, layer::{InputLayer, DenseLayer}, metrics::MeanAbsoluteError, dataset::Dataset};
+
+    #[test]
+    fn one_record_per_epoch() {
+        let inputs: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64 / 8.0, 1.0 - i as f64 / 8.0]).collect();
+        let truths: Vec<Vec<f64>> = inputs.iter().map(|x| vec![x[0] - 0.5 * x[1]]).collect();
+        let val = Dataset::new(&inputs[..3], &truths[..3]);
+
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 2])).add(DenseLayer::new(1)).add_metric(MeanAbsoluteError);
+        model.compile_with_seed(3);
+        let history = model.train(&inputs, &truths, 3, 4, 0.1, Some(&val));
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.epochs.iter().map(|e| e.epoch).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(history.loss().iter().all(|l| l.is_finite() && *l > 0.0));
+        assert!(history.metric("mae").iter().all(|m| m.is_some()));
+        assert!(history.epochs.iter().all(|e| e.learning_rate == 0.1));
+
+        // validation runs after the last update, so it matches an evaluation of the trained model
+        let last = &history.epochs[2];
+        let eval = model.evaluate(&val.inputs, &val.truths);
+        assert_eq!(last.val_loss, Some(eval.loss));
+        assert_eq!(last.get("val_mae"), Some(eval.metrics[0].1));
+        assert_eq!(last.get("loss"), Some(last.loss));
+        assert_eq!(history.to_csv().lines().next(), Some("epoch,loss,mae,val_loss,val_mae,learning_rate,time"));
+        assert_eq!(history.to_csv().lines().count(), 4);
+    }
+}
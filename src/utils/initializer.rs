@@ -0,0 +1,142 @@
+use rand::rngs::StdRng;
+
+use super::shape::Array;
+
+/* how the parameters of a layer are drawn,
+ * fan_in / fan_out are the inputs / outputs feeding one unit (kernel area times channels for convolutions)
+ */
+#[derive(Clone, Debug)]
+pub enum Initializer {
+    Zeros,
+    Constant(f64),
+    Uniform(f64, f64),              // [low, high)
+    Normal(f64, f64),               // (mean, std)
+    TruncatedNormal(f64, f64),      // (mean, std), redrawn beyond 2 std
+    GlorotUniform,                  // U(-l, l), l = sqrt(6 / (fan_in + fan_out))
+    GlorotNormal,                   // N(0, 2 / (fan_in + fan_out))
+    HeUniform,                      // U(-l, l), l = sqrt(6 / fan_in)
+    HeNormal,                       // N(0, 2 / fan_in)
+    LeCunUniform,                   // U(-l, l), l = sqrt(3 / fan_in)
+    LeCunNormal,                    // N(0, 1 / fan_in)
+    Orthogonal(f64),                // gain times a (semi-)orthogonal matrix of [fan_in, fan_out]
+}
+
+impl Initializer {
+    pub fn initialize(&self, shape: &[usize], fan_in: usize, fan_out: usize, rng: &mut StdRng) -> Array<f64> {
+        let fan_in = fan_in.max(1) as f64;
+        let fan_out = fan_out.max(1) as f64;
+        match *self {
+            Initializer::Zeros => Array::<f64>::zeros(shape),
+            Initializer::Constant(v) => Array::<f64>::fill(shape, v),
+            Initializer::Uniform(low, high) => Array::<f64>::random_with(shape, low, high, rng),
+            Initializer::Normal(mean, std) => Array::<f64>::normal_with(shape, mean, std, rng),
+            Initializer::TruncatedNormal(mean, std) => Array::<f64>::truncated_normal_with(shape, mean, std, rng),
+            Initializer::GlorotUniform => uniform(shape, (6.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initializer::GlorotNormal => Array::<f64>::normal_with(shape, 0.0, (2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Initializer::HeUniform => uniform(shape, (6.0 / fan_in).sqrt(), rng),
+            Initializer::HeNormal => Array::<f64>::normal_with(shape, 0.0, (2.0 / fan_in).sqrt(), rng),
+            Initializer::LeCunUniform => uniform(shape, (3.0 / fan_in).sqrt(), rng),
+            Initializer::LeCunNormal => Array::<f64>::normal_with(shape, 0.0, (1.0 / fan_in).sqrt(), rng),
+            Initializer::Orthogonal(gain) => orthogonal(shape, gain, rng),
+        }
+    }
+}
+
+fn uniform(shape: &[usize], limit: f64, rng: &mut StdRng) -> Array<f64> {
+    Array::<f64>::random_with(shape, -limit, limit, rng)
+}
+
+/* flatten to [rows, cols] with cols the last dimension, orthonormalize the longer side with Gram-Schmidt */
+fn orthogonal(shape: &[usize], gain: f64, rng: &mut StdRng) -> Array<f64> {
+    let cols = *shape.last().unwrap();
+    let rows: usize = shape.iter().product::<usize>() / cols;
+    let (n, m) = (rows.max(cols), rows.min(cols));
+
+    // m random vectors of length n
+    let mut q: Vec<Vec<f64>> = (0..m).map(|_| Array::<f64>::normal_with(&[n], 0.0, 1.0, rng).into_vec()).collect();
+    for i in 0..m {
+        for j in 0..i {
+            let d: f64 = (0..n).map(|k| q[i][k] * q[j][k]).sum();
+            for k in 0..n {
+                q[i][k] -= d * q[j][k];
+            }
+        }
+        let norm = q[i].iter().map(|x| x * x).sum::<f64>().sqrt();
+        q[i].iter_mut().for_each(|x| *x /= norm);
+    }
+
+    let mut a = Array::<f64>::zeros(shape);
+    for r in 0..rows {
+        for c in 0..cols {
+            // columns are orthonormal when rows >= cols, rows otherwise
+            a.data[r * cols + c] = gain * if rows >= cols { q[c][r] } else { q[r][c] };
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const FAN_IN: usize = 50;
+    const FAN_OUT: usize = 30;
+
+    /* mean and variance of 20000 draws with the fans above */
+    fn moments(initializer: Initializer) -> (f64, f64, Array<f64>) {
+        let a = initializer.initialize(&[400, 50], FAN_IN, FAN_OUT, &mut StdRng::seed_from_u64(5));
+        let n = a.data.len() as f64;
+        let mean = a.data.iter().sum::<f64>() / n;
+        let var = a.data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        (mean, var, a)
+    }
+
+    fn assert_moments(initializer: Initializer, var: f64) {
+        let (m, v, _) = moments(initializer.clone());
+        // 4 standard errors of the mean, 5% of the variance
+        assert!(m.abs() < 4.0 * (var / 20000.0).sqrt(), "{:?} mean {}", initializer, m);
+        assert!((v / var - 1.0).abs() < 0.05, "{:?} variance {} expected {}", initializer, v, var);
+    }
+
+    #[test]
+    fn uniform_variants() {
+        let (fan_in, fan_out) = (FAN_IN as f64, FAN_OUT as f64);
+        for (initializer, limit) in [
+            (Initializer::GlorotUniform, (6.0 / (fan_in + fan_out)).sqrt()),
+            (Initializer::HeUniform, (6.0 / fan_in).sqrt()),
+            (Initializer::LeCunUniform, (3.0 / fan_in).sqrt()),
+        ] {
+            assert_moments(initializer.clone(), limit * limit / 3.0);
+            let (_, _, a) = moments(initializer);
+            assert!(a.data.iter().all(|x| x.abs() <= limit));
+            // the draws reach close to the limit
+            assert!(a.data.iter().fold(0.0f64, |m, x| m.max(x.abs())) > 0.99 * limit);
+        }
+    }
+
+    #[test]
+    fn normal_variants() {
+        let (fan_in, fan_out) = (FAN_IN as f64, FAN_OUT as f64);
+        assert_moments(Initializer::GlorotNormal, 2.0 / (fan_in + fan_out));
+        assert_moments(Initializer::HeNormal, 2.0 / fan_in);
+        assert_moments(Initializer::LeCunNormal, 1.0 / fan_in);
+    }
+
+    #[test]
+    fn orthogonal() {
+        // the shorter side is orthonormal, scaled by the gain
+        for shape in [[6, 4], [4, 6], [5, 5]] {
+            let a = Initializer::Orthogonal(2.0).initialize(&shape, shape[0], shape[1], &mut StdRng::seed_from_u64(5));
+            let gram = if shape[0] >= shape[1] { a.t().dot(&a) } else { a.dot(&a.t()) };
+            let m = shape[0].min(shape[1]);
+            for i in 0..m {
+                for j in 0..m {
+                    let expected = if i == j { 4.0 } else { 0.0 };
+                    assert!((gram.data[i * m + j] - expected).abs() < 1e-10, "{:?} gram[{}][{}] = {}", shape, i, j, gram.data[i * m + j]);
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,91 @@
 use std::mem::replace;
+use rand::{rngs::StdRng, SeedableRng};
 
-use super::{ops::{ Sigmoid, ReLU, Operator, calculate, TanH, ReLU6 }, shape::Array};
+use super::{ops::{Activation, activation_by_name}, shape::Array, initializer::Initializer, regularizer::{Regularizer, Constraint}};
+
+// convolutions of every rank share one implementation
+pub use super::convolution::Conv2DLayer;
 
 pub trait Layer {
     fn forward_prop(&mut self, input: Array<f64>) -> Array<f64>;
     fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>);
     fn config_shape(&mut self, prev_output_shape: &[usize]);
+    /* draw the initial parameters, called by compile after config_shape */
+    fn init_parameters(&mut self, _rng: &mut StdRng) {}
     fn update_parameters(&mut self, _delta_weights: &Array<f64>, _delta_bias: &Array<f64>) {}
+    /* layers gathering sparse gradients in backward_prop instead of returning deltas add scale * gradient here */
+    fn apply_sparse_gradients(&mut self, _scale: f64) {}
     fn set_parameters(&mut self, _weights: Array<f64>, _bias: Array<f64>) {}
-    fn get_output_shape(&self) -> &Box<[usize]>;
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> { None }
+    /* weight penalty added to the loss of every sample */
+    fn regularization_loss(&self) -> f64 { 0.0 }
+    /* switch between training (true) and inference (false) behavior */
+    fn set_training(&mut self, _training: bool) {}
+    /* seed the random draws of the layer, called by the model before every training batch */
+    fn reseed(&mut self, _seed: u64) {}
+    /* truncated backpropagation through time, set by the model before training */
+    fn set_truncation(&mut self, _steps: Option<usize>) {}
+    /* padding mask of a [timesteps, n] input, false for padded timesteps, set by the model before every forward_prop,
+     * layers that skip padded timesteps keep it, the others ignore it
+     */
+    fn set_mask(&mut self, _mask: Option<&[bool]>) {}
+    /* mask of the output after forward_prop, by default the input mask as long as the output keeps the timesteps */
+    fn output_mask(&self, mask: Option<Vec<bool>>) -> Option<Vec<bool>> {
+        mask.filter(|m| self.get_output_shape().first() == Some(&m.len()))
+    }
+    /* non-trainable state such as running statistics, saved with the weights */
+    fn get_state(&self) -> Vec<Array<f64>> { Vec::new() }
+    fn set_state(&mut self, _state: Vec<Array<f64>>) {}
+    /* accepts a whole mini-batch stacked along the first axis of a [1, n] shape */
+    fn batchable(&self) -> bool { false }
+    /* normalizes over the samples of a mini-batch, so the model must stack them */
+    fn batch_statistics(&self) -> bool { false }
+    fn get_output_shape(&self) -> &[usize];
+}
+
+/* panics unless the input has the configured shape */
+pub fn check_input_shape(name: &str, expected: &[usize], input: &[usize]) {
+    if expected.len() != input.len() {
+        panic!("[{}] input dim not match.", name);
+    }
+    if expected != input {
+        panic!("[{}] input shape not match.", name);
+    }
+}
+
+/* check_input_shape of batchable layers, a configured [1, n] shape also takes a stacked [batch, n] mini-batch */
+pub fn check_batch_input_shape(name: &str, expected: &[usize], input: &[usize]) {
+    if expected.len() == 2 && expected[0] == 1 && input.len() == 2 && input[0] > 0 {
+        check_input_shape(name, &expected[1..], &input[1..]);
+    } else {
+        check_input_shape(name, expected, input);
+    }
+}
+
+/* border handling of windowed (pooling, convolution) layers */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    Valid,  // windows stay inside the input
+    Same,   // output size is ceil(size / stride), the border is split evenly with the extra cell after
+}
+
+impl Padding {
+    /* (output size, padding before) of one axis */
+    pub fn output_size(&self, size: usize, window: usize, stride: usize) -> (usize, usize) {
+        match self {
+            Padding::Valid => {
+                if size < window {
+                    panic!("[Padding] window {} larger than input {}.", window, size);
+                }
+                ((size - window) / stride + 1, 0)
+            }
+            Padding::Same => {
+                let out = size.div_ceil(stride);
+                let total = ((out - 1) * stride + window).saturating_sub(size);
+                (out, total / 2)
+            }
+        }
+    }
 }
 
 pub struct InputLayer {
@@ -37,75 +114,83 @@ impl Layer for InputLayer {
     }
 
     fn config_shape(&mut self, _prev_output_shape: &[usize]) {}
-    fn get_output_shape(&self) -> &Box<[usize]> {
+
+    fn batchable(&self) -> bool {
+        true
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
         &self.output_shape
     }
 }
 
-macro_rules! new_activation_layer {
-    ($struct:ident, $type:ident) => {
-        pub struct $struct {
-            pub input: Array<f64>,
-            pub input_shape: Box<[usize]>,
-            pub output_shape: Box<[usize]>,
+/* element-wise activation, e.g. ActivationLayer::new(ReLU) or ActivationLayer::from_name("leaky_relu(0.2)") */
+pub struct ActivationLayer {
+    pub activation: Box<dyn Activation>,
+    pub input: Array<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl ActivationLayer {
+    pub fn new<A: Activation + 'static>(activation: A) -> Self {
+        ActivationLayer::with_boxed(Box::new(activation))
+    }
+
+    pub fn with_boxed(activation: Box<dyn Activation>) -> Self {
+        ActivationLayer {
+            activation,
+            input: Array::<f64>::empty(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
         }
+    }
 
-        #[allow(dead_code)]
-        impl $struct {
-            pub fn new() -> Self {
-                $struct {
-                    input: Array::<f64>::empty(),
-                    input_shape: Box::default(),
-                    output_shape: Box::default(),
-                }
-            }
+    /* panics on an unknown name, see ops::activation_by_name */
+    pub fn from_name(name: &str) -> Self {
+        match activation_by_name(name) {
+            Some(activation) => ActivationLayer::with_boxed(activation),
+            None => panic!("[Activ] unknown activation \"{}\".", name),
         }
+    }
+}
 
-        impl Layer for $struct {
-            fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
-                if self.input_shape.len() == input.shape.len() {
-                    for i in 0..input.shape.len() {
-                        if self.input_shape[i] != input.shape[i] {
-                            panic!("[Activ] input shape not match.");
-                        }
-                    }
-                } else {
-                    panic!("[Activ] input dim not match.");
-                }
-                
-                self.input = input;
-                calculate(self.input.clone(), $type::activation)
-            }
-        
-            fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
-                let mut input = Array::<f64>::empty();
-                input = replace(&mut self.input, input);
-                let mut deriv = calculate(input, $type::derivative);
-                for i in 0..error.sub_size[0] {
-                    deriv.data[i] = deriv.data[i] * error.data[i];
-                }
-                (deriv, None, None)
-            }
-        
-            fn config_shape(&mut self, prev_output_shape: &[usize]) {
-                self.input_shape = prev_output_shape.into();
-                self.output_shape = prev_output_shape.into();
-                println!("[Activ] config i/o shape: {:?}", self.input_shape);
-            }
+impl Layer for ActivationLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_batch_input_shape("Activ", &self.input_shape, &input.shape);
 
-            fn get_output_shape(&self) -> &Box<[usize]> {
-                &self.output_shape
-            }
+        let mut output = input.clone();
+        output.data.iter_mut().for_each(|x| *x = self.activation.activation(*x));
+        self.input = input;
+        output
+    }
+
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let input = replace(&mut self.input, Array::<f64>::empty());
+        for (e, x) in error.data.iter_mut().zip(input.data.iter()) {
+            *e *= self.activation.derivative(*x);
         }
-    };
-}
+        (error, None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[Activ] config i/o shape: {:?}, {}", self.input_shape, self.activation.name());
+    }
 
-new_activation_layer!(SigmoidLayer, Sigmoid);
-new_activation_layer!(ReLULayer, ReLU);
-new_activation_layer!(ReLU6Layer, ReLU6);
-new_activation_layer!(TanHLayer, TanH);
+    fn batchable(&self) -> bool {
+        true
+    }
 
-pub struct DenseLayer {
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* leaky ReLU with a learned slope per channel (last axis), the slopes are the weights and the bias is empty */
+pub struct PReLULayer {
+    pub alpha: f64, // initial slope
     pub input: Array<f64>,
     pub weights: Array<f64>,
     pub bias: Array<f64>,
@@ -113,159 +198,575 @@ pub struct DenseLayer {
     pub output_shape: Box<[usize]>,
 }
 
-impl DenseLayer {
-    pub fn new(output_size: usize) -> Self {
-        DenseLayer {
+impl PReLULayer {
+    pub fn new() -> Self {
+        PReLULayer {
+            alpha: 0.25,
             input: Array::<f64>::empty(),
             weights: Array::<f64>::empty(),
-            bias: Array::<f64>::zeros(&[1, output_size]),
+            bias: Array::<f64>::empty(),
             input_shape: Box::default(),
-            output_shape: Box::new([1, output_size]),
+            output_shape: Box::default(),
         }
     }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
 }
 
-impl Layer for DenseLayer {
+impl Default for PReLULayer {
+    fn default() -> Self {
+        PReLULayer::new()
+    }
+}
+
+impl Layer for PReLULayer {
     fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
-        if self.input_shape.len() == input.shape.len() {
-            for i in 0..input.shape.len() {
-                if self.input_shape[i] != input.shape[i] {
-                    panic!("[Dense] input shape not match.");
-                }
+        check_batch_input_shape("PReLU", &self.input_shape, &input.shape);
+
+        let ch = self.weights.data.len();
+        let mut output = input.clone();
+        for (i, x) in output.data.iter_mut().enumerate() {
+            if *x <= 0.0 {
+                *x *= self.weights.data[i % ch];
             }
-        } else {
-            panic!("[Dense] input dim not match.");
         }
-
         self.input = input;
-        let mut w = self.input.dot(&self.weights);
-        w.add_m(&self.bias);
-        w
+        output
     }
 
-    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
-        let input_error = error.dot(&self.weights.t());
-        let weights_error = self.input.t().dot(&error);
-
-        (input_error, Some(weights_error), Some(error))
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let ch = self.weights.data.len();
+        let mut weights_error = Array::<f64>::zeros(&self.weights.shape);
+        for (i, e) in error.data.iter_mut().enumerate() {
+            let x = self.input.data[i];
+            if x <= 0.0 {
+                weights_error.data[i % ch] += *e * x;
+                *e *= self.weights.data[i % ch];
+            }
+        }
+        (error, Some(weights_error), Some(Array::<f64>::empty()))
     }
 
-    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, _delta_bias: &Array<f64>) {
         self.weights.add_m(delta_weights);
-        self.bias.add_m(delta_bias);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, _bias: Array<f64>) {
+        if self.weights.shape != weights.shape {
+            panic!("[PReLU] slope shape not match.");
+        }
+        self.weights = weights;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        let ch = prev_output_shape[prev_output_shape.len() - 1];
+        self.weights = Array::<f64>::fill(&[1, ch], self.alpha);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[PReLU] config i/o shape: {:?}, channels: {}", self.input_shape, ch);
+    }
+
+    fn batchable(&self) -> bool {
+        true
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* inverted dropout: while training, zero each input with probability `rate` and scale the rest by 1 / (1 - rate) */
+pub struct DropoutLayer {
+    pub rate: f64,
+    pub mask: Array<f64>,
+    pub training: bool,
+    pub rng: StdRng,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl DropoutLayer {
+    pub fn new(rate: f64) -> Self {
+        assert!((0.0..1.0).contains(&rate), "[Dropout] rate must be in [0, 1).");
+        DropoutLayer {
+            rate,
+            mask: Array::<f64>::empty(),
+            training: false,
+            rng: StdRng::seed_from_u64(0),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+}
+
+impl Layer for DropoutLayer {
+    fn forward_prop(&mut self, mut input: Array<f64>) -> Array<f64> {
+        if !self.training {
+            return input;
+        }
+
+        let keep = 1.0 - self.rate;
+        self.mask = Array::<f64>::bernoulli_with(&input.shape, keep, &mut self.rng);
+        self.mask.mul_v(1.0 / keep);
+        for i in 0..input.data.len() {
+            input.data[i] *= self.mask.data[i];
+        }
+        input
+    }
+
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        if self.training {
+            for i in 0..error.data.len() {
+                error.data[i] *= self.mask.data[i];
+            }
+        }
+        (error, None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[Dropout] config i/o shape: {:?}", self.input_shape);
+    }
+
+    fn batchable(&self) -> bool {
+        true
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* dropout for SELU networks: dropped inputs are set to the SELU saturation value,
+ * then an affine transform keeps the mean and variance of the activations
+ */
+pub struct AlphaDropoutLayer {
+    pub rate: f64,
+    pub mask: Array<f64>,
+    pub training: bool,
+    pub rng: StdRng,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl AlphaDropoutLayer {
+    pub fn new(rate: f64) -> Self {
+        assert!((0.0..1.0).contains(&rate), "[AlphaDropout] rate must be in [0, 1).");
+        AlphaDropoutLayer {
+            rate,
+            mask: Array::<f64>::empty(),
+            training: false,
+            rng: StdRng::seed_from_u64(0),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    /* -scale * alpha of SELU */
+    const ALPHA_P: f64 = -1.758_099_340_847_376_6;
+
+    /* (a, b) of the affine transform for the keep probability */
+    fn affine(&self) -> (f64, f64) {
+        let keep = 1.0 - self.rate;
+        let a = (keep + Self::ALPHA_P * Self::ALPHA_P * keep * self.rate).powf(-0.5);
+        (a, -a * Self::ALPHA_P * self.rate)
+    }
+}
+
+impl Layer for AlphaDropoutLayer {
+    fn forward_prop(&mut self, mut input: Array<f64>) -> Array<f64> {
+        if !self.training {
+            return input;
+        }
+
+        let (a, b) = self.affine();
+        self.mask = Array::<f64>::bernoulli_with(&input.shape, 1.0 - self.rate, &mut self.rng);
+        for i in 0..input.data.len() {
+            let m = self.mask.data[i];
+            input.data[i] = a * (input.data[i] * m + Self::ALPHA_P * (1.0 - m)) + b;
+        }
+        input
+    }
+
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        if self.training {
+            let (a, _) = self.affine();
+            for i in 0..error.data.len() {
+                error.data[i] *= a * self.mask.data[i];
+            }
+        }
+        (error, None, None)
     }
 
     fn config_shape(&mut self, prev_output_shape: &[usize]) {
         self.input_shape = prev_output_shape.into();
-        self.weights = Array::<f64>::random_default(&[prev_output_shape[1], self.output_shape[1]]);
-        println!("[Dense] config shape: {:?}", self.weights.shape);
+        self.output_shape = prev_output_shape.into();
+        println!("[AlphaDropout] config i/o shape: {:?}", self.input_shape);
+    }
+
+    fn batchable(&self) -> bool {
+        true
     }
 
-    fn get_output_shape(&self) -> &Box<[usize]> {
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
         &self.output_shape
     }
 }
 
-pub struct Conv2DLayer {
-    pub kernel_size: usize,
+pub struct DenseLayer {
     pub input: Array<f64>,
+    pub activation: Option<Box<dyn Activation>>,
+    pub activated: Array<f64>, // activation output, or its input if the derivative needs that
     pub weights: Array<f64>,
     pub bias: Array<f64>,
     pub input_shape: Box<[usize]>,
     pub output_shape: Box<[usize]>,
+    pub initializer: Initializer,
+    pub bias_initializer: Initializer,
+    pub regularizer: Option<Regularizer>,
+    pub bias_regularizer: Option<Regularizer>,
+    pub constraint: Option<Constraint>,
+    pub bias_constraint: Option<Constraint>,
 }
 
-impl Conv2DLayer {
-    pub fn new(output_channel: usize, kernel_size: usize) -> Self {
-        Conv2DLayer {
-            kernel_size,
+impl DenseLayer {
+    pub fn new(output_size: usize) -> Self {
+        DenseLayer {
             input: Array::<f64>::empty(),
-            // weights: Array::<f64>::random(&[input_shape[0] - kernel_size + 1, input_shape[1] - kernel_size + 1, output_channel]),
+            activation: None,
+            activated: Array::<f64>::empty(),
             weights: Array::<f64>::empty(),
-            bias: Array::<f64>::zeros(&[output_channel]),
+            bias: Array::<f64>::zeros(&[1, output_size]),
             input_shape: Box::default(),
-            output_shape: Box::default(),
+            output_shape: Box::new([1, output_size]),
+            initializer: Initializer::GlorotUniform,
+            bias_initializer: Initializer::Zeros,
+            regularizer: None,
+            bias_regularizer: None,
+            constraint: None,
+            bias_constraint: None,
         }
     }
+
+    /* dense layer followed by `activation` in the same pass, like DenseLayer::new(n) + ActivationLayer::new(activation) */
+    pub fn with_activation<A: Activation + 'static>(output_size: usize, activation: A) -> Self {
+        let mut layer = DenseLayer::new(output_size);
+        layer.activation = Some(Box::new(activation));
+        layer
+    }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    pub fn with_bias_initializer(mut self, initializer: Initializer) -> Self {
+        self.bias_initializer = initializer;
+        self
+    }
+
+    pub fn with_regularizer(mut self, regularizer: Regularizer) -> Self {
+        self.regularizer = Some(regularizer);
+        self
+    }
+
+    pub fn with_bias_regularizer(mut self, regularizer: Regularizer) -> Self {
+        self.bias_regularizer = Some(regularizer);
+        self
+    }
+
+    pub fn with_constraint(mut self, constraint: Constraint) -> Self {
+        self.constraint = Some(constraint);
+        self
+    }
+
+    pub fn with_bias_constraint(mut self, constraint: Constraint) -> Self {
+        self.bias_constraint = Some(constraint);
+        self
+    }
 }
 
-impl Layer for Conv2DLayer {
+impl Layer for DenseLayer {
     fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
-        let o_rows = self.output_shape[0];
-        let o_cols = self.output_shape[1];
-        let o_ch = self.weights.shape[3];
-        let i_ch = self.weights.shape[2];
-
-        let k_size = self.kernel_size;
-
-        let mut res: Array<f64> = Array::<f64>::zeros(&[o_rows, o_cols, o_ch]);
-
-        for i in 0..o_rows {
-            for j in 0..o_cols {
-                for k in 0..o_ch {
-                    for ki in 0..k_size {
-                        for kj in 0..k_size {
-                            for ik in 0..i_ch {
-                                res[&[i, j, k]] += self.weights[&[ki, kj, ik, k]] * input[&[i + ki, j + kj, ik]];
-                            }
-                        }
-                    }
-                    res[&[i, j, k]] += self.bias[&[k, 0]];
-                }
+        check_batch_input_shape("Dense", &self.input_shape, &input.shape);
+
+        self.input = input;
+        let mut w = self.input.dot(&self.weights);
+        // bias is added to every row
+        let cols = self.bias.data.len();
+        for i in 0..w.data.len() {
+            w.data[i] += self.bias.data[i % cols];
+        }
+        if let Some(act) = &self.activation {
+            if act.has_output_derivative() {
+                w.data.iter_mut().for_each(|x| *x = act.activation(*x));
+                self.activated = w.clone();
+            } else {
+                self.activated = w.clone();
+                w.data.iter_mut().for_each(|x| *x = act.activation(*x));
             }
         }
-        res
+        w
     }
 
-    fn backward_prop(&mut self, _error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
-        // TODO!
-        // dL/dX = full-conv(weights.rotate(180), error)
-        // dL/dW = conv(input, error)
-        // dL/dB = sum(error)
-        (Array::<f64>::empty(), None, None)
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        if let Some(act) = &self.activation {
+            let activated = replace(&mut self.activated, Array::<f64>::empty());
+            let output = act.has_output_derivative();
+            for (e, a) in error.data.iter_mut().zip(activated.data.iter()) {
+                *e *= if output { act.derivative_from_output(*a) } else { act.derivative(*a) };
+            }
+        }
+        let input_error = error.dot(&self.weights.t());
+        let mut weights_error = self.input.t().dot(&error);
+        let cols = self.bias.data.len();
+        let mut bias_error = Array::<f64>::zeros(&self.bias.shape);
+        for i in 0..error.data.len() {
+            bias_error.data[i % cols] += error.data[i];
+        }
+        add_regularization(&mut weights_error, &self.weights, &self.regularizer);
+        add_regularization(&mut bias_error, &self.bias, &self.bias_regularizer);
+
+        (input_error, Some(weights_error), Some(bias_error))
     }
 
     fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
         self.weights.add_m(delta_weights);
         self.bias.add_m(delta_bias);
+        apply_constraints(&mut self.weights, &self.constraint, &mut self.bias, &self.bias_constraint);
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        regularization_loss(&self.weights, &self.regularizer, &self.bias, &self.bias_regularizer)
     }
 
     fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
-        if self.weights.shape.len() == weights.shape.len() {
-            for i in 0..weights.shape.len() {
-                if self.weights.shape[i] != weights.shape[i] {
-                    panic!("[Conv2D] weights shape not match.");
-                }
-            }
-        } else {
-            panic!("[Conv2D] weights dim not match.");
+        if self.weights.shape != weights.shape {
+            panic!("[Dense] weights shape not match.");
         }
-        self.weights = weights;
-
-        if bias.shape.len() != 2 || bias.sub_size[0] != self.bias.sub_size[0] {
-            panic!("[Conv2D] bias dim/shape not match.");
+        if self.bias.shape != bias.shape {
+            panic!("[Dense] bias shape not match.");
         }
+        self.weights = weights;
         self.bias = bias;
     }
 
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
     fn config_shape(&mut self, prev_output_shape: &[usize]) {
-        self.weights = Array::<f64>::random_default(&[
-            self.kernel_size, self.kernel_size,
-            prev_output_shape[2], // input channel
-            self.bias.sub_size[0] // output channel
-        ]);
-        
         self.input_shape = prev_output_shape.into();
-        self.output_shape = Box::new([
-            prev_output_shape[0] - self.kernel_size + 1, // output rows
-            prev_output_shape[1] - self.kernel_size + 1, // output cols
-            self.bias.sub_size[0] // output channel
-        ]);
+        // applied to every row, e.g. to each timestep of [timesteps, features]
+        self.output_shape = Box::new([prev_output_shape[0], self.output_shape[1]]);
+        self.weights = Array::<f64>::zeros(&[prev_output_shape[1], self.output_shape[1]]);
+        match &self.activation {
+            Some(act) => println!("[Dense] config shape: {:?}, {}", self.weights.shape, act.name()),
+            None => println!("[Dense] config shape: {:?}", self.weights.shape),
+        }
+    }
 
-        println!("[Conv2D] config shape:\n\tI: {:?} \n\tO: {:?} \n\tW: {:?}", self.input_shape, self.output_shape, self.weights.shape);
+    fn batchable(&self) -> bool {
+        true
     }
 
-    fn get_output_shape(&self) -> &Box<[usize]> {
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        let (fan_in, fan_out) = (self.weights.shape[0], self.weights.shape[1]);
+        self.weights = self.initializer.initialize(&self.weights.shape, fan_in, fan_out, rng);
+        self.bias = self.bias_initializer.initialize(&self.bias.shape, fan_in, fan_out, rng);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
         &self.output_shape
     }
 }
+
+
+pub fn add_regularization(delta: &mut Array<f64>, params: &Array<f64>, regularizer: &Option<Regularizer>) {
+    if let Some(r) = regularizer {
+        delta.add_m(&r.gradient(params));
+    }
+}
+
+pub fn regularization_loss(weights: &Array<f64>, regularizer: &Option<Regularizer>, bias: &Array<f64>, bias_regularizer: &Option<Regularizer>) -> f64 {
+    regularizer.as_ref().map_or(0.0, |r| r.penalty(weights)) + bias_regularizer.as_ref().map_or(0.0, |r| r.penalty(bias))
+}
+
+pub fn apply_constraints(weights: &mut Array<f64>, constraint: &Option<Constraint>, bias: &mut Array<f64>, bias_constraint: &Option<Constraint>) {
+    if let Some(c) = constraint {
+        c.apply(weights);
+    }
+    if let Some(c) = bias_constraint {
+        c.apply(bias);
+    }
+}
+
+/* several arrays as one [1, n] parameter array, for layers made of sub-layers or with more than weights and bias,
+ * the empty array when there is nothing to pack
+ */
+pub fn pack_parameters(parts: &[&Array<f64>]) -> Array<f64> {
+    let data: Vec<f64> = parts.iter().flat_map(|p| p.data.iter().copied()).collect();
+    if data.is_empty() {
+        return Array::<f64>::empty();
+    }
+    Array::<f64>::with(&[1, data.len()], &data)
+}
+
+/* split an array of pack_parameters() back into arrays of the given shapes */
+pub fn unpack_parameters(packed: &Array<f64>, shapes: &[&[usize]]) -> Vec<Array<f64>> {
+    let sizes: Vec<usize> = shapes.iter().map(|s| if s.is_empty() { 0 } else { s.iter().product() }).collect();
+    if sizes.iter().sum::<usize>() != packed.data.len() {
+        panic!("[Layer] packed parameters do not match the shapes.");
+    }
+    let mut start = 0;
+    shapes.iter().zip(sizes).map(|(shape, size)| {
+        let part = &packed.data[start..start + size];
+        start += size;
+        if shape.is_empty() { Array::<f64>::empty() } else { Array::<f64>::with(shape, part) }
+    }).collect()
+}
+
+/* the parameters of sub-layers packed into one weights and one bias array, None if no sub-layer has parameters */
+pub fn pack_sublayer_parameters(layers: &[&dyn Layer]) -> Option<(Array<f64>, Array<f64>)> {
+    let params: Vec<(Array<f64>, Array<f64>)> = layers.iter().filter_map(|l| l.get_parameters()).collect();
+    if params.is_empty() {
+        return None;
+    }
+    let weights: Vec<&Array<f64>> = params.iter().map(|p| &p.0).collect();
+    let bias: Vec<&Array<f64>> = params.iter().map(|p| &p.1).collect();
+    Some((pack_parameters(&weights), pack_parameters(&bias)))
+}
+
+/* set weights and bias of pack_sublayer_parameters() on the sub-layers with parameters */
+pub fn set_sublayer_parameters(layers: &mut [&mut dyn Layer], weights: Array<f64>, bias: Array<f64>) {
+    let parts = split_sublayer_parameters(layers, &weights, &bias);
+    for (l, (w, b)) in layers.iter_mut().filter(|l| l.get_parameters().is_some()).zip(parts) {
+        l.set_parameters(w, b);
+    }
+}
+
+/* update the sub-layers with deltas packed like pack_sublayer_parameters() */
+pub fn update_sublayer_parameters(layers: &mut [&mut dyn Layer], delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+    let parts = split_sublayer_parameters(layers, delta_weights, delta_bias);
+    for (l, (dw, db)) in layers.iter_mut().filter(|l| l.get_parameters().is_some()).zip(parts) {
+        l.update_parameters(&dw, &db);
+    }
+}
+
+fn split_sublayer_parameters(layers: &[&mut dyn Layer], weights: &Array<f64>, bias: &Array<f64>) -> Vec<(Array<f64>, Array<f64>)> {
+    let params: Vec<(Array<f64>, Array<f64>)> = layers.iter().filter_map(|l| l.get_parameters()).collect();
+    let w: Vec<&[usize]> = params.iter().map(|p| &p.0.shape[..]).collect();
+    let b: Vec<&[usize]> = params.iter().map(|p| &p.1.shape[..]).collect();
+    unpack_parameters(weights, &w).into_iter().zip(unpack_parameters(bias, &b)).collect()
+}
+
+/* the deltas of backward_prop() on sub-layers packed like pack_sublayer_parameters() */
+pub fn pack_sublayer_deltas(weights: Vec<Option<Array<f64>>>, bias: Vec<Option<Array<f64>>>) -> (Option<Array<f64>>, Option<Array<f64>>) {
+    let weights: Vec<Array<f64>> = weights.into_iter().flatten().collect();
+    let bias: Vec<Array<f64>> = bias.into_iter().flatten().collect();
+    if weights.is_empty() {
+        return (None, None);
+    }
+    (Some(pack_parameters(&weights.iter().collect::<Vec<_>>())), Some(pack_parameters(&bias.iter().collect::<Vec<_>>())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    #[test]
+    fn dense_gradients() {
+        check_layer(&mut DenseLayer::new(3), &[1, 4], true);
+    }
+
+    #[test]
+    fn prelu_gradients() {
+        check_layer(&mut PReLULayer::new(), &[1, 5], true);
+    }
+
+    /* 20000 standard normal values */
+    fn normal_input() -> Array<f64> {
+        Array::<f64>::normal_with(&[100, 200], 0.0, 1.0, &mut StdRng::seed_from_u64(3))
+    }
+
+    fn mean_var(a: &Array<f64>) -> (f64, f64) {
+        let n = a.data.len() as f64;
+        let mean = a.data.iter().sum::<f64>() / n;
+        (mean, a.data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n)
+    }
+
+    #[test]
+    fn dropout() {
+        let mut layer = DropoutLayer::new(0.25);
+        layer.config_shape(&[100, 200]);
+        let input = Array::<f64>::fill(&[100, 200], 2.0);
+        assert_eq!(layer.forward_prop(input.clone()).data, input.data);
+
+        layer.set_training(true);
+        layer.reseed(1);
+        let output = layer.forward_prop(input.clone());
+        // the kept inputs are scaled by 1 / (1 - rate), so the mean is kept
+        assert!(output.data.iter().all(|&x| x == 0.0 || x == 2.0 / 0.75));
+        let dropped = output.data.iter().filter(|&&x| x == 0.0).count() as f64 / output.data.len() as f64;
+        assert!((dropped - 0.25).abs() < 0.01, "{}", dropped);
+        assert!((mean_var(&output).0 - 2.0).abs() < 0.02);
+
+        // the error goes through the same mask
+        let (error, _, _) = layer.backward_prop(Array::<f64>::fill(&[100, 200], 1.0));
+        assert!(error.data.iter().zip(output.data.iter()).all(|(e, o)| *e == o / 2.0));
+
+        // the same seed draws the same mask
+        layer.reseed(1);
+        assert_eq!(layer.forward_prop(input).data, output.data);
+    }
+
+    #[test]
+    fn alpha_dropout() {
+        let mut layer = AlphaDropoutLayer::new(0.2);
+        layer.config_shape(&[100, 200]);
+        let input = normal_input();
+        assert_eq!(layer.forward_prop(input.clone()).data, input.data);
+
+        layer.set_training(true);
+        let output = layer.forward_prop(input);
+        // zero mean and unit variance are kept
+        let (mean, var) = mean_var(&output);
+        assert!(mean.abs() < 0.03 && (var - 1.0).abs() < 0.05, "mean {} variance {}", mean, var);
+
+        let (a, b) = layer.affine();
+        let dropped = a * AlphaDropoutLayer::ALPHA_P + b;
+        let (error, _, _) = layer.backward_prop(Array::<f64>::fill(&[100, 200], 1.0));
+        for (e, (o, m)) in error.data.iter().zip(output.data.iter().zip(layer.mask.data.iter())) {
+            if *m == 0.0 {
+                assert!(*e == 0.0 && (o - dropped).abs() < 1e-12);
+            } else {
+                assert_eq!(*e, a);
+            }
+        }
+    }
+}
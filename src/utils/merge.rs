@@ -0,0 +1,211 @@
+use super::shape::Array;
+
+/* layer joining the outputs of several nodes of a graph Model, e.g. the end of a skip connection */
+pub trait MergeLayer {
+    fn forward_prop(&mut self, inputs: Vec<Array<f64>>) -> Array<f64>;
+    /* error of every input, in the order of the inputs */
+    fn backward_prop(&mut self, error: Array<f64>) -> Vec<Array<f64>>;
+    fn config_shape(&mut self, prev_output_shapes: &[&[usize]]);
+    fn get_output_shape(&self) -> &[usize];
+}
+
+/* element-wise reduction of a merge layer */
+#[derive(Clone, Copy, Debug)]
+enum Elementwise {
+    Add,
+    Average,
+    Multiply,
+}
+
+/* element-wise merge of inputs of the same shape */
+macro_rules! new_elementwise_merge_layer {
+    ($struct:ident, $op:expr, $name:literal) => {
+        pub struct $struct {
+            pub inputs: Vec<Array<f64>>, // kept for the product rule of Multiply
+            pub input_count: usize,
+            pub output_shape: Box<[usize]>,
+        }
+
+        impl $struct {
+            pub fn new() -> Self {
+                $struct {
+                    inputs: Vec::new(),
+                    input_count: 0,
+                    output_shape: Box::default(),
+                }
+            }
+        }
+
+        impl Default for $struct {
+            fn default() -> Self {
+                $struct::new()
+            }
+        }
+
+        impl MergeLayer for $struct {
+            fn forward_prop(&mut self, inputs: Vec<Array<f64>>) -> Array<f64> {
+                if inputs.len() != self.input_count {
+                    panic!("[{}] expects {} inputs, got {}.", $name, self.input_count, inputs.len());
+                }
+                let mut output = inputs[0].clone();
+                for input in inputs[1..].iter() {
+                    if input.shape != output.shape {
+                        panic!("[{}] inputs of different shapes {:?} and {:?}.", $name, output.shape, input.shape);
+                    }
+                    match $op {
+                        Elementwise::Add | Elementwise::Average => { output.add_m(input); }
+                        Elementwise::Multiply => output.data.iter_mut().zip(input.data.iter()).for_each(|(o, x)| *o *= x),
+                    }
+                }
+                match $op {
+                    Elementwise::Average => { output.mul_v(1.0 / self.input_count as f64); }
+                    Elementwise::Multiply => self.inputs = inputs,
+                    Elementwise::Add => {}
+                }
+                output
+            }
+
+            fn backward_prop(&mut self, mut error: Array<f64>) -> Vec<Array<f64>> {
+                match $op {
+                    Elementwise::Add => vec![error; self.input_count],
+                    Elementwise::Average => {
+                        error.mul_v(1.0 / self.input_count as f64);
+                        vec![error; self.input_count]
+                    }
+                    Elementwise::Multiply => (0..self.input_count).map(|i| {
+                        // the error times the product of the other inputs
+                        let mut e = error.clone();
+                        for (j, input) in self.inputs.iter().enumerate() {
+                            if j != i {
+                                e.data.iter_mut().zip(input.data.iter()).for_each(|(e, x)| *e *= x);
+                            }
+                        }
+                        e
+                    }).collect(),
+                }
+            }
+
+            fn config_shape(&mut self, prev_output_shapes: &[&[usize]]) {
+                if prev_output_shapes.len() < 2 {
+                    panic!("[{}] needs at least two inputs.", $name);
+                }
+                if prev_output_shapes.iter().any(|s| *s != prev_output_shapes[0]) {
+                    panic!("[{}] inputs of different shapes {:?}.", $name, prev_output_shapes);
+                }
+                self.input_count = prev_output_shapes.len();
+                self.output_shape = prev_output_shapes[0].into();
+                println!("[{}] config {} inputs of shape {:?}", $name, self.input_count, self.output_shape);
+            }
+
+            fn get_output_shape(&self) -> &[usize] {
+                &self.output_shape
+            }
+        }
+    };
+}
+
+new_elementwise_merge_layer!(AddLayer, Elementwise::Add, "Add");
+new_elementwise_merge_layer!(AverageLayer, Elementwise::Average, "Average");
+new_elementwise_merge_layer!(MultiplyLayer, Elementwise::Multiply, "Multiply");
+
+/* inputs joined along `axis`, the last one by default, the other dimensions must agree */
+pub struct ConcatenateLayer {
+    pub axis: Option<usize>,
+    pub input_shapes: Vec<Box<[usize]>>,
+    pub parts: Vec<usize>, // size of each input from the axis on
+    pub output_shape: Box<[usize]>,
+}
+
+impl ConcatenateLayer {
+    pub fn new() -> Self {
+        ConcatenateLayer {
+            axis: None,
+            input_shapes: Vec::new(),
+            parts: Vec::new(),
+            output_shape: Box::default(),
+        }
+    }
+
+    pub fn with_axis(mut self, axis: usize) -> Self {
+        self.axis = Some(axis);
+        self
+    }
+}
+
+impl Default for ConcatenateLayer {
+    fn default() -> Self {
+        ConcatenateLayer::new()
+    }
+}
+
+impl MergeLayer for ConcatenateLayer {
+    fn forward_prop(&mut self, inputs: Vec<Array<f64>>) -> Array<f64> {
+        if inputs.len() != self.parts.len() {
+            panic!("[Concatenate] expects {} inputs, got {}.", self.parts.len(), inputs.len());
+        }
+        // the dimensions before the axis repeat the parts `outer` times
+        let outer = inputs[0].data.len() / self.parts[0];
+        let mut data = Vec::with_capacity(self.output_shape.iter().product());
+        for o in 0..outer {
+            for (input, &part) in inputs.iter().zip(self.parts.iter()) {
+                data.extend_from_slice(&input.data[o * part..(o + 1) * part]);
+            }
+        }
+        Array::<f64>::with(&self.output_shape, &data)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> Vec<Array<f64>> {
+        let width: usize = self.parts.iter().sum();
+        let mut start = 0;
+        self.parts.iter().zip(self.input_shapes.iter()).map(|(&part, shape)| {
+            let data: Vec<f64> = error.data.chunks(width).flat_map(|row| row[start..start + part].iter().copied()).collect();
+            start += part;
+            Array::<f64>::with(shape, &data)
+        }).collect()
+    }
+
+    fn config_shape(&mut self, prev_output_shapes: &[&[usize]]) {
+        if prev_output_shapes.len() < 2 {
+            panic!("[Concatenate] needs at least two inputs.");
+        }
+        let dims = prev_output_shapes[0].len();
+        let axis = self.axis.unwrap_or(dims - 1);
+        if axis >= dims {
+            panic!("[Concatenate] axis {} out of {} dimensions.", axis, dims);
+        }
+        for shape in prev_output_shapes.iter() {
+            if shape.len() != dims || (0..dims).any(|d| d != axis && shape[d] != prev_output_shapes[0][d]) {
+                panic!("[Concatenate] shapes {:?} differ beyond axis {}.", prev_output_shapes, axis);
+            }
+        }
+        self.input_shapes = prev_output_shapes.iter().map(|&s| s.into()).collect();
+        self.parts = prev_output_shapes.iter().map(|s| s[axis..].iter().product()).collect();
+        let mut shape = prev_output_shapes[0].to_vec();
+        shape[axis] = prev_output_shapes.iter().map(|s| s[axis]).sum();
+        self.output_shape = shape.into();
+        println!("[Concatenate] config shapes: {:?}, output shape: {:?}", prev_output_shapes, self.output_shape);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_merge_layer;
+
+    #[test]
+    fn elementwise_gradients() {
+        check_merge_layer(&mut AddLayer::new(), &[&[2, 3], &[2, 3]]);
+        check_merge_layer(&mut AverageLayer::new(), &[&[2, 3], &[2, 3], &[2, 3]]);
+        check_merge_layer(&mut MultiplyLayer::new(), &[&[2, 3], &[2, 3], &[2, 3]]);
+    }
+
+    #[test]
+    fn concatenate_gradients() {
+        check_merge_layer(&mut ConcatenateLayer::new(), &[&[2, 3], &[2, 1]]);
+        check_merge_layer(&mut ConcatenateLayer::new().with_axis(0), &[&[2, 3], &[1, 3]]);
+    }
+}
@@ -0,0 +1,439 @@
+use super::dataset::arg_max;
+
+pub trait Metric {
+    fn name(&self) -> String;
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64;
+}
+
+/* how per-class scores are reduced to one number */
+#[derive(Clone, Copy, Debug)]
+pub enum Average {
+    Macro,          // unweighted mean over classes
+    Micro,          // computed from the summed counts of all classes
+    Class(usize),   // score of a single class
+}
+
+/* confusion matrix, counts[truth][predict] */
+#[derive(Clone, Debug)]
+pub struct ConfusionMatrix {
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    /* build from predicted scores and one-hot truths, the class is the arg max,
+     * a single output is binary with class 1 above 0.5
+     */
+    pub fn new(truth: &[Vec<f64>], predict: &[Vec<f64>]) -> Self {
+        assert!(truth.len() == predict.len(), "[Metric] truth and predict have different lengths.");
+        let width = truth.first().map_or(0, |t| t.len());
+        let classes = if width == 1 { 2 } else { width };
+        let mut counts = vec![vec![0; classes]; classes];
+        for (t, p) in truth.iter().zip(predict.iter()) {
+            check_width(t, p, width);
+            counts[class_of(t)][class_of(p)] += 1;
+        }
+        ConfusionMatrix { counts }
+    }
+
+    pub fn classes(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|r| r.iter().sum::<usize>()).sum()
+    }
+
+    pub fn true_positives(&self, class: usize) -> usize {
+        self.counts[class][class]
+    }
+
+    pub fn false_positives(&self, class: usize) -> usize {
+        (0..self.classes()).map(|t| self.counts[t][class]).sum::<usize>() - self.counts[class][class]
+    }
+
+    pub fn false_negatives(&self, class: usize) -> usize {
+        self.counts[class].iter().sum::<usize>() - self.counts[class][class]
+    }
+
+    pub fn precision(&self, class: usize) -> f64 {
+        ratio(self.true_positives(class), self.true_positives(class) + self.false_positives(class))
+    }
+
+    pub fn recall(&self, class: usize) -> f64 {
+        ratio(self.true_positives(class), self.true_positives(class) + self.false_negatives(class))
+    }
+
+    pub fn f1(&self, class: usize) -> f64 {
+        harmonic_mean(self.precision(class), self.recall(class))
+    }
+
+    pub fn precision_per_class(&self) -> Vec<f64> {
+        (0..self.classes()).map(|c| self.precision(c)).collect()
+    }
+
+    pub fn recall_per_class(&self) -> Vec<f64> {
+        (0..self.classes()).map(|c| self.recall(c)).collect()
+    }
+
+    pub fn f1_per_class(&self) -> Vec<f64> {
+        (0..self.classes()).map(|c| self.f1(c)).collect()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        ratio((0..self.classes()).map(|c| self.true_positives(c)).sum(), self.total())
+    }
+
+    /* with one label per sample micro precision, recall and f1 all equal the accuracy */
+    fn reduce(&self, average: Average, score: fn(&Self, usize) -> f64) -> f64 {
+        match average {
+            Average::Macro => (0..self.classes()).map(|c| score(self, c)).sum::<f64>() / self.classes() as f64,
+            Average::Micro => self.accuracy(),
+            Average::Class(c) => score(self, c),
+        }
+    }
+}
+
+fn class_of(v: &[f64]) -> usize {
+    if v.len() == 1 {
+        (v[0] > 0.5) as usize
+    } else {
+        arg_max(v)
+    }
+}
+
+fn check_width(truth: &[f64], predict: &[f64], width: usize) {
+    if truth.len() != width || predict.len() != width {
+        panic!("[Metric] truth of width {} and predict of width {}, expected {}.", truth.len(), predict.len(), width);
+    }
+}
+
+fn ratio(num: usize, den: usize) -> f64 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    }
+}
+
+fn harmonic_mean(a: f64, b: f64) -> f64 {
+    if a + b == 0.0 {
+        0.0
+    } else {
+        2.0 * a * b / (a + b)
+    }
+}
+
+pub struct Accuracy;
+
+/* truth class is among the k highest scores */
+pub struct TopKAccuracy {
+    pub k: usize,
+}
+
+pub struct Precision {
+    pub average: Average,
+}
+
+pub struct Recall {
+    pub average: Average,
+}
+
+pub struct F1Score {
+    pub average: Average,
+}
+
+/* area under the ROC curve for binary outputs,
+ * the positive score is the single output or the second of two outputs,
+ * NaN when the truths hold only one class
+ */
+pub struct RocAuc;
+
+impl Metric for Accuracy {
+    fn name(&self) -> String {
+        "accuracy".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        ConfusionMatrix::new(truth, predict).accuracy()
+    }
+}
+
+impl Metric for TopKAccuracy {
+    fn name(&self) -> String {
+        format!("top_{}_accuracy", self.k)
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let mut hits = 0;
+        for (t, p) in truth.iter().zip(predict.iter()) {
+            check_width(t, p, t.len());
+            let score = p[arg_max(t)];
+            // rank of the truth class, ties counted in its favour
+            let higher = p.iter().filter(|&&s| s > score).count();
+            if higher < self.k {
+                hits += 1;
+            }
+        }
+        ratio(hits, truth.len())
+    }
+}
+
+impl Metric for Precision {
+    fn name(&self) -> String {
+        average_name("precision", self.average)
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        ConfusionMatrix::new(truth, predict).reduce(self.average, ConfusionMatrix::precision)
+    }
+}
+
+impl Metric for Recall {
+    fn name(&self) -> String {
+        average_name("recall", self.average)
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        ConfusionMatrix::new(truth, predict).reduce(self.average, ConfusionMatrix::recall)
+    }
+}
+
+impl Metric for F1Score {
+    fn name(&self) -> String {
+        average_name("f1", self.average)
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        ConfusionMatrix::new(truth, predict).reduce(self.average, ConfusionMatrix::f1)
+    }
+}
+
+impl Metric for RocAuc {
+    fn name(&self) -> String {
+        "roc_auc".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let positive = |v: &Vec<f64>| if v.len() == 1 { v[0] } else { v[1] };
+        let mut samples: Vec<(f64, bool)> = truth.iter().zip(predict.iter())
+            .map(|(t, p)| (positive(p), positive(t) > 0.5))
+            .collect();
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Mann-Whitney U statistic, tied scores share their average rank
+        let mut rank_sum = 0.0;
+        let mut i = 0;
+        while i < samples.len() {
+            let mut j = i;
+            while j < samples.len() && samples[j].0 == samples[i].0 {
+                j += 1;
+            }
+            let rank = (i + j + 1) as f64 / 2.0;
+            rank_sum += rank * samples[i..j].iter().filter(|s| s.1).count() as f64;
+            i = j;
+        }
+
+        let pos = samples.iter().filter(|s| s.1).count() as f64;
+        let neg = samples.len() as f64 - pos;
+        if pos == 0.0 || neg == 0.0 {
+            return f64::NAN;
+        }
+        (rank_sum - pos * (pos + 1.0) / 2.0) / (pos * neg)
+    }
+}
+
+fn average_name(metric: &str, average: Average) -> String {
+    match average {
+        Average::Macro => format!("macro_{}", metric),
+        Average::Micro => format!("micro_{}", metric),
+        Average::Class(c) => format!("{}_{}", metric, c),
+    }
+}
+
+/* coefficient of determination, averaged over the outputs */
+pub struct RSquared;
+
+/* like R² but ignoring a constant bias of the prediction, averaged over the outputs */
+pub struct ExplainedVariance;
+
+pub struct RootMeanSquaredError;
+
+pub struct MeanAbsoluteError;
+
+/* fraction, not percent; truths close to zero are clamped to f64::EPSILON */
+pub struct MeanAbsolutePercentageError;
+
+pub struct MedianAbsoluteError;
+
+/* all (truth, predict) pairs of all outputs */
+fn pairs<'a>(truth: &'a [Vec<f64>], predict: &'a [Vec<f64>]) -> impl Iterator<Item = (f64, f64)> + 'a {
+    assert!(truth.len() == predict.len(), "[Metric] truth and predict have different lengths.");
+    truth.iter().zip(predict.iter()).flat_map(|(t, p)| t.iter().copied().zip(p.iter().copied()))
+}
+
+/* mean over the outputs of 1 - unexplained(residual) / var(truth) */
+fn mean_variance_score(truth: &[Vec<f64>], predict: &[Vec<f64>], unexplained: fn(&[f64]) -> f64) -> f64 {
+    let outputs = truth.first().map_or(0, |t| t.len());
+    let mut score = 0.0;
+    for o in 0..outputs {
+        let t: Vec<f64> = truth.iter().map(|v| v[o]).collect();
+        let p: Vec<f64> = predict.iter().map(|v| v[o]).collect();
+        let residual: Vec<f64> = t.iter().zip(p.iter()).map(|(t, p)| t - p).collect();
+        let var = variance(&t);
+        let num = unexplained(&residual);
+        // a constant truth is perfectly explained only by a perfect prediction
+        score += if var == 0.0 {
+            if num == 0.0 { 1.0 } else { 0.0 }
+        } else {
+            1.0 - num / var
+        };
+    }
+    score / outputs as f64
+}
+
+fn mean(v: &[f64]) -> f64 {
+    v.iter().sum::<f64>() / v.len() as f64
+}
+
+fn variance(v: &[f64]) -> f64 {
+    let m = mean(v);
+    v.iter().map(|x| (x - m).powi(2)).sum::<f64>() / v.len() as f64
+}
+
+impl Metric for RSquared {
+    fn name(&self) -> String {
+        "r2".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        mean_variance_score(truth, predict, |residual| {
+            residual.iter().map(|r| r * r).sum::<f64>() / residual.len() as f64
+        })
+    }
+}
+
+impl Metric for ExplainedVariance {
+    fn name(&self) -> String {
+        "explained_variance".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        mean_variance_score(truth, predict, variance)
+    }
+}
+
+impl Metric for RootMeanSquaredError {
+    fn name(&self) -> String {
+        "rmse".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let sq: Vec<f64> = pairs(truth, predict).map(|(t, p)| (t - p).powi(2)).collect();
+        mean(&sq).sqrt()
+    }
+}
+
+impl Metric for MeanAbsoluteError {
+    fn name(&self) -> String {
+        "mae".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let abs: Vec<f64> = pairs(truth, predict).map(|(t, p)| (t - p).abs()).collect();
+        mean(&abs)
+    }
+}
+
+impl Metric for MeanAbsolutePercentageError {
+    fn name(&self) -> String {
+        "mape".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let abs: Vec<f64> = pairs(truth, predict).map(|(t, p)| (t - p).abs() / t.abs().max(f64::EPSILON)).collect();
+        mean(&abs)
+    }
+}
+
+impl Metric for MedianAbsoluteError {
+    fn name(&self) -> String {
+        "median_absolute_error".to_string()
+    }
+    fn calculate(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> f64 {
+        let mut abs: Vec<f64> = pairs(truth, predict).map(|(t, p)| (t - p).abs()).collect();
+        if abs.is_empty() {
+            return f64::NAN;
+        }
+        abs.sort_by(|a, b| a.total_cmp(b));
+        let mid = abs.len() / 2;
+        if abs.len() % 2 == 0 {
+            (abs[mid - 1] + abs[mid]) / 2.0
+        } else {
+            abs[mid]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(v: &[&[f64]]) -> Vec<Vec<f64>> {
+        v.iter().map(|r| r.to_vec()).collect()
+    }
+
+    #[test]
+    fn binary_single_output() {
+        let truth = rows(&[&[1.0], &[0.0], &[1.0], &[1.0], &[0.0]]);
+        let predict = rows(&[&[0.9], &[0.2], &[0.4], &[0.7], &[0.6]]);
+        let cm = ConfusionMatrix::new(&truth, &predict);
+        assert_eq!(cm.counts, vec![vec![1, 1], vec![1, 2]]);
+        assert_eq!(Accuracy.calculate(&truth, &predict), 0.6);
+        assert_eq!(Precision { average: Average::Class(1) }.calculate(&truth, &predict), 2.0 / 3.0);
+        assert_eq!(Recall { average: Average::Class(1) }.calculate(&truth, &predict), 2.0 / 3.0);
+        assert!((F1Score { average: Average::Class(1) }.calculate(&truth, &predict) - 2.0 / 3.0).abs() < 1e-12);
+        // positive scores 0.9, 0.4, 0.7 against negative 0.2, 0.6: 5 of 6 pairs ordered
+        assert!((RocAuc.calculate(&truth, &predict) - 5.0 / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn roc_auc_of_one_class_is_nan() {
+        assert!(RocAuc.calculate(&rows(&[&[1.0], &[1.0]]), &rows(&[&[0.3], &[0.8]])).is_nan());
+    }
+
+    #[test]
+    fn multiclass() {
+        let truth = rows(&[&[1.0, 0.0, 0.0], &[0.0, 1.0, 0.0], &[0.0, 0.0, 1.0], &[0.0, 1.0, 0.0]]);
+        let predict = rows(&[&[0.8, 0.1, 0.1], &[0.3, 0.6, 0.1], &[0.5, 0.2, 0.3], &[0.1, 0.7, 0.2]]);
+        let cm = ConfusionMatrix::new(&truth, &predict);
+        assert_eq!(cm.counts, vec![vec![1, 0, 0], vec![0, 2, 0], vec![1, 0, 0]]);
+        assert_eq!(Accuracy.calculate(&truth, &predict), 0.75);
+        assert_eq!(cm.precision_per_class(), vec![0.5, 1.0, 0.0]);
+        assert_eq!(cm.recall_per_class(), vec![1.0, 1.0, 0.0]);
+        assert_eq!(Precision { average: Average::Macro }.calculate(&truth, &predict), 0.5);
+        assert_eq!(Recall { average: Average::Micro }.calculate(&truth, &predict), 0.75);
+        assert!((F1Score { average: Average::Macro }.calculate(&truth, &predict) - (2.0 / 3.0 + 1.0) / 3.0).abs() < 1e-12);
+        assert_eq!(TopKAccuracy { k: 2 }.calculate(&truth, &predict), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "truth of width 1 and predict of width 2")]
+    fn width_mismatch_panics() {
+        ConfusionMatrix::new(&rows(&[&[1.0]]), &rows(&[&[0.2, 0.8]]));
+    }
+
+    #[test]
+    fn regression() {
+        let truth = rows(&[&[1.0], &[2.0], &[3.0], &[4.0]]);
+        let predict = rows(&[&[1.5], &[2.0], &[2.0], &[5.0]]);
+        // residuals -0.5, 0, 1, -1, truth variance 1.25
+        assert_eq!(MeanAbsoluteError.calculate(&truth, &predict), 0.625);
+        assert_eq!(RootMeanSquaredError.calculate(&truth, &predict), (2.25f64 / 4.0).sqrt());
+        assert_eq!(MedianAbsoluteError.calculate(&truth, &predict), 0.75);
+        assert!((MeanAbsolutePercentageError.calculate(&truth, &predict) - (0.5 + 0.0 + 1.0 / 3.0 + 0.25) / 4.0).abs() < 1e-12);
+        assert!((RSquared.calculate(&truth, &predict) - (1.0 - 0.5625 / 1.25)).abs() < 1e-12);
+        // residual mean -0.125, variance 0.546875
+        assert!((ExplainedVariance.calculate(&truth, &predict) - (1.0 - 0.546875 / 1.25)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn regression_edge_cases() {
+        let constant = rows(&[&[2.0], &[2.0]]);
+        assert_eq!(RSquared.calculate(&constant, &constant), 1.0);
+        assert_eq!(RSquared.calculate(&constant, &rows(&[&[2.0], &[3.0]])), 0.0);
+        assert!(MedianAbsoluteError.calculate(&[], &[]).is_nan());
+        // two outputs are averaged, each with R² 1 and 0
+        let truth = rows(&[&[1.0, 0.0], &[3.0, 2.0]]);
+        let predict = rows(&[&[1.0, 1.0], &[3.0, 1.0]]);
+        assert_eq!(RSquared.calculate(&truth, &predict), 0.5);
+    }
+}
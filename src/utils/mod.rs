@@ -3,4 +3,22 @@ pub mod ops;
 pub mod loss;
 pub mod shape;
 pub mod model;
-pub mod dataset;
\ No newline at end of file
+pub mod dataset;
+pub mod cross_validation;
+pub mod metrics;
+pub mod history;
+pub mod callback;
+pub mod random;
+pub mod initializer;
+pub mod regularizer;
+pub mod normalization;
+pub mod pooling;
+pub mod reshaping;
+pub mod recurrent;
+pub mod embedding;
+pub mod attention;
+pub mod convolution;
+pub mod merge;
+pub mod graph;
+#[cfg(test)]
+pub mod gradient_check;
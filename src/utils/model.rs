@@ -1,9 +1,25 @@
+use std::{time::Instant, mem::take, fs, io, iter::Peekable};
+use rand::Rng;
+
 use crate::utils::{loss::{MSE, Loss}, shape::Array};
-use super::layer::Layer;
+use super::{layer::Layer, metrics::Metric, dataset::Dataset, history::{History, EpochRecord}, callback::Callback, random::{stream_rng, derive_seed}};
 
 #[derive(Default)]
 pub struct Sequential  {
     pub layers: Vec<Box<dyn Layer>>,
+    pub metrics: Vec<Box<dyn Metric>>,
+    pub callbacks: Vec<Box<dyn Callback>>,
+    pub stop_training: bool, // set by callbacks to end training after the current batch
+    pub state: TrainState,
+    pub seed: u64,     // drives initialization and every random draw during training
+    pub shuffle: bool, // reorder the samples every epoch
+    pub truncated_bptt: Option<usize>, // timesteps recurrent layers backpropagate through, all if None
+    pub stacked_batches: bool, // train [1, n] models on whole [batch, n] mini-batches, see set_stacked_batches
+}
+
+pub struct Evaluation {
+    pub loss: f64,
+    pub metrics: Vec<(String, f64)>,
 }
 
 impl Sequential  {
@@ -19,102 +35,766 @@ impl Sequential  {
         self
     }
 
-    /* config input_shape for each layer */
+    /* register a metric reported by evaluate() and on validation data during training */
+    pub fn add_metric<M>(&mut self, metric: M) -> &mut Self
+    where
+        M: Metric + 'static,
+    {
+        self.metrics.push(Box::new(metric));
+        self
+    }
+
+    /* register a callback invoked around training, epochs and batches */
+    pub fn add_callback<C>(&mut self, callback: C) -> &mut Self
+    where
+        C: Callback + 'static,
+    {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /* shuffle the training samples every epoch */
+    pub fn set_shuffle(&mut self, shuffle: bool) -> &mut Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /* truncated backpropagation through time: recurrent layers cut the gradient every `steps` timesteps,
+     * counted back from the last one
+     */
+    pub fn set_truncated_bptt(&mut self, steps: Option<usize>) -> &mut Self {
+        assert!(steps != Some(0), "[Model] truncated bptt needs at least one step.");
+        self.truncated_bptt = steps;
+        self
+    }
+
+    /* push each mini-batch through the layers as one [batch, n] array instead of sample by sample,
+     * only models of [1, n] input and batchable layers can stack, off by default as it changes the rounding and
+     * random draws of every update, compile turns it on for layers normalizing over the batch (BatchNormLayer)
+     */
+    pub fn set_stacked_batches(&mut self, stacked: bool) -> &mut Self {
+        self.stacked_batches = stacked;
+        self
+    }
+
+    /* config input_shape for each layer, with a random seed */
     pub fn compile(&mut self) {
+        self.compile_with_seed(rand::random());
+    }
+
+    /* config input_shape for each layer and initialize the parameters,
+     * the same seed gives the same weights and the same training run
+     */
+    pub fn compile_with_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        let mut rng = stream_rng(seed, 0);
         for l in 1..self.layers.len() {
-            let prev_output_shape = self.layers[l - 1].get_output_shape().clone();
+            let prev_output_shape = self.layers[l - 1].get_output_shape().to_vec();
             self.layers[l].config_shape(&prev_output_shape);
+            self.layers[l].init_parameters(&mut rng);
+        }
+
+        if let Some(l) = self.layers.iter().position(|l| l.batch_statistics()) {
+            self.stacked_batches = true;
+            if !self.batchable() {
+                panic!("[Model] layer {} normalizes over the batch, but the model can not stack [batch, n] mini-batches.", l);
+            }
+            println!("[Model] stacked batches on for the batch statistics of layer {}", l);
+        }
+    }
+
+    /* summed weight penalties of all layers */
+    pub fn regularization_loss(&self) -> f64 {
+        self.layers.iter().map(|l| l.regularization_loss()).sum()
+    }
+
+    /* run one sample through all layers, the padding mask of each output is handed to the next layer */
+    fn forward(&mut self, input: &[f64]) -> Array<f64> {
+        let mut temp_input = Array::<f64>::with(self.layers[0].get_output_shape(), input);
+        let mut mask = None;
+        for l in self.layers.iter_mut() {
+            l.set_mask(mask.as_deref());
+            temp_input = l.forward_prop(temp_input);
+            mask = l.output_mask(mask);
         }
+        temp_input
+    }
+
+    /* switch every layer between training and inference behavior */
+    pub fn set_training(&mut self, training: bool) {
+        self.layers.iter_mut().for_each(|l| l.set_training(training));
     }
 
     /* make prediction */
     pub fn predict(&mut self, input: &[Vec<f64>]) -> Vec<Vec<f64>> {
-        let len = input.len();
-        let mut output: Vec<Vec<f64>> = Vec::default();
-        for i in 0..len {
-            let mut temp_input = Array::<f64>::with(self.layers[0].get_output_shape(), &input[i]);
-            let mut temp_out: Array<f64>;
-            for l in self.layers.iter_mut() {
-                temp_out = l.forward_prop(temp_input);
-                temp_input = temp_out;
-            }
-            output.push(temp_input.to_vec());
+        self.set_training(false);
+        input.iter().map(|sample| self.forward(sample).into_vec()).collect()
+    }
+
+    /* loss and metrics on the given samples, weights are left untouched */
+    pub fn evaluate(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>]) -> Evaluation {
+        assert!(!input.is_empty() && truth.len() == input.len());
+
+        self.set_training(false);
+        let mut loss = 0.0;
+        let mut predict: Vec<Vec<f64>> = Vec::with_capacity(input.len());
+        for (sample, t) in input.iter().zip(truth.iter()) {
+            let output = self.forward(sample);
+            loss += MSE::calculate(t, &output);
+            predict.push(output.into_vec());
         }
-        output
+        loss = loss / input.len() as f64 + self.regularization_loss();
+
+        let metrics = self.metrics.iter().map(|m| (m.name(), m.calculate(truth, &predict))).collect();
+        Evaluation { loss, metrics }
     }
 
-    pub fn train(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>], epoches: usize, batch_size: usize, learning_rate: f64) {
-        let sample_len = input.len();
+    /* stacking is on and the mini-batch can go through the layers as one [batch, n] array */
+    fn batchable(&self) -> bool {
+        if !self.stacked_batches {
+            return false;
+        }
+        let shape = self.layers[0].get_output_shape();
+        shape.len() == 2 && shape[0] == 1 && self.layers.iter().all(|l| l.batchable())
+    }
 
-        assert!(sample_len > 0 && truth.len() == sample_len && batch_size > 0 && learning_rate > 0.0);
+    /* forward and backward the samples at `batch`, apply the summed gradients and return the summed error,
+     * the outputs are pushed to `predictions` when the model has metrics
+     */
+    fn train_batch(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Vec<f64>>) -> f64 {
+        if self.batchable() {
+            return self.train_stacked_batch(input, truth, batch, learning_rate, predictions);
+        }
 
-        for epoch in 0..epoches {
-            let mut err = 0.0; // error on all samples
-            let iters: usize = sample_len / batch_size;
+        let mut err = 0.0;
+        let mut vec_delta_weights: Vec<Option<Array<f64>>> = Vec::default();
+        let mut vec_delta_bias: Vec<Option<Array<f64>>> = Vec::default();
+        let layer_len = self.layers.len();
+        for (b, &index) in batch.iter().enumerate() {
+            let layer_input = self.forward(&input[index]);
+            err += MSE::calculate(&truth[index], &layer_input);
+            if !self.metrics.is_empty() {
+                predictions.push(layer_input.data.to_vec());
+            }
 
-            for i in 0..iters + 1 {
-                let mut bs = batch_size;
-                // for leftover
-                if i == iters {
-                    bs = sample_len % batch_size;
-                }
+            // backward propagation
+            let loss = MSE::derivative(&truth[index], layer_input);
+            let mut back_input = loss;
+            let mut back_output: Array<f64>;
+            let mut delta_weights: Option<Array<f64>>;
+            let mut delta_bias: Option<Array<f64>>;
 
-                if bs > 0 {
-                    let mut vec_delta_weights: Vec<Option<Array<f64>>> = Vec::default();
-                    let mut vec_delta_bias: Vec<Option<Array<f64>>> = Vec::default();
-                    let layer_len = self.layers.len();
-                    for b in 0..bs {
-                        let mut layer_input = Array::<f64>::with(self.layers[0].get_output_shape(), &input[i]);
-                        let mut layer_output: Array<f64>;
-                        
-                        for l in self.layers.iter_mut() {
-                            layer_output = l.forward_prop(layer_input);
-                            layer_input = layer_output;
-                        }
-                        err += MSE::calculate(&truth[i], &layer_input);
-                        
-                        // backward propagation
-                        let loss = MSE::derivative(&truth[i], layer_input);
-                        let mut back_input = loss;
-                        let mut back_output: Array<f64>;
-                        let mut delta_weights: Option<Array<f64>>;
-                        let mut delta_bias: Option<Array<f64>>;
-
-                        
-                        for l in 0..layer_len {
-                            (back_output, delta_weights, delta_bias) = self.layers[layer_len - 1 - l].backward_prop(back_input);
-
-                            if b == 0 {
-                                vec_delta_weights.push(delta_weights);
-                                vec_delta_bias.push(delta_bias);
-                            } else {
-                                if let Some(w) = delta_weights {
-                                    let vl = vec_delta_weights[l].as_mut().unwrap();
-                                    vl.add_m(&w);
-                                }
-                                if let Some(b) = delta_bias {
-                                    let vl = vec_delta_bias[l].as_mut().unwrap();
-                                    vl.add_m(&b);
-                                }
-                            }
-                            
-                            back_input = back_output;
-                        }
-                    }
+            for l in 0..layer_len {
+                (back_output, delta_weights, delta_bias) = self.layers[layer_len - 1 - l].backward_prop(back_input);
 
-                    for l in 0..layer_len {
-                        if let Some(_) = &vec_delta_weights[l] {
-                            self.layers[layer_len - 1 - l].update_parameters(
-                                vec_delta_weights[l].as_mut().unwrap().mul_v(-learning_rate),
-                                vec_delta_bias[l].as_mut().unwrap().mul_v(-learning_rate)
-                            );
-                        }
+                if b == 0 {
+                    vec_delta_weights.push(delta_weights);
+                    vec_delta_bias.push(delta_bias);
+                } else {
+                    if let Some(w) = delta_weights {
+                        let vl = vec_delta_weights[l].as_mut().unwrap();
+                        vl.add_m(&w);
+                    }
+                    if let Some(b) = delta_bias {
+                        let vl = vec_delta_bias[l].as_mut().unwrap();
+                        vl.add_m(&b);
                     }
                 }
+
+                back_input = back_output;
             }
+        }
+
+        self.apply_deltas(vec_delta_weights, vec_delta_bias, -learning_rate);
+        err
+    }
 
-            err /= sample_len as f64;
-            println!("epoch {}/{}, error: {:.6}", epoch + 1, epoches, err);
+    /* same as train_batch with the samples stacked into one [batch, n] array,
+     * layers like batch normalization see the statistics of the whole batch
+     */
+    fn train_stacked_batch(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Vec<f64>>) -> f64 {
+        let bs = batch.len();
+        let cols = self.layers[0].get_output_shape()[1];
+        let stacked_input: Vec<f64> = batch.iter().flat_map(|&i| input[i].iter().copied()).collect();
+        let stacked_truth: Vec<f64> = batch.iter().flat_map(|&i| truth[i].iter().copied()).collect();
+
+        let mut layer_input = Array::<f64>::with(&[bs, cols], &stacked_input);
+        for l in self.layers.iter_mut() {
+            layer_input = l.forward_prop(layer_input);
+        }
+        let err = MSE::calculate(&stacked_truth, &layer_input) * bs as f64;
+        if !self.metrics.is_empty() {
+            predictions.extend(layer_input.data.chunks(layer_input.data.len() / bs).map(|p| p.to_vec()));
+        }
+
+        // the loss gradient is that of the batch mean, so deltas are scaled by bs to match the summed per-sample deltas
+        let mut back_input = MSE::derivative(&stacked_truth, layer_input);
+        let mut vec_delta_weights: Vec<Option<Array<f64>>> = Vec::default();
+        let mut vec_delta_bias: Vec<Option<Array<f64>>> = Vec::default();
+        for l in self.layers.iter_mut().rev() {
+            let (back_output, delta_weights, delta_bias) = l.backward_prop(back_input);
+            vec_delta_weights.push(delta_weights);
+            vec_delta_bias.push(delta_bias);
+            back_input = back_output;
         }
+
+        self.apply_deltas(vec_delta_weights, vec_delta_bias, -learning_rate * bs as f64);
+        err
+    }
+
+    /* add scale * deltas to the parameters, deltas are in reverse layer order */
+    fn apply_deltas(&mut self, mut vec_delta_weights: Vec<Option<Array<f64>>>, mut vec_delta_bias: Vec<Option<Array<f64>>>, scale: f64) {
+        let layer_len = self.layers.len();
+        for l in 0..layer_len {
+            if vec_delta_weights[l].is_some() {
+                self.layers[layer_len - 1 - l].update_parameters(
+                    vec_delta_weights[l].as_mut().unwrap().mul_v(scale),
+                    vec_delta_bias[l].as_mut().unwrap().mul_v(scale)
+                );
+            }
+        }
+        self.layers.iter_mut().for_each(|l| l.apply_sparse_gradients(scale));
+    }
+
+    /* train with mini-batch gradient descent, `validation` is evaluated after every epoch */
+    pub fn train(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>], epoches: usize, batch_size: usize, learning_rate: f64,
+        validation: Option<&Dataset>) -> History {
+        if batch_size < 2 && self.layers.iter().any(|l| l.batch_statistics()) {
+            panic!("[Model] batch statistics need a batch size of 2 or more.");
+        }
+        self.state = TrainState { epoches, batch_size, learning_rate, seed: self.seed, ..TrainState::default() };
+        fit(self, input, truth, validation.map(|v| (v.inputs.as_slice(), v.truths.as_slice())))
+    }
+
+    /* continue a run from a checkpoint written by save_checkpoint(), on the same data and with the same callbacks,
+     * the weights, losses and callback decisions then follow the uninterrupted run exactly,
+     * even from a checkpoint written mid-epoch; only the training metrics of a resumed epoch miss the batches
+     * done before the checkpoint
+     */
+    pub fn resume(&mut self, file_name: &str, input: &[Vec<f64>], truth: &[Vec<f64>], validation: Option<&Dataset>) -> Result<History, io::Error> {
+        self.load_checkpoint(file_name)?;
+        self.seed = self.state.seed;
+        println!("[Model] resume from epoch {}/{}, batch {}, step {}", self.state.epoch, self.state.epoches, self.state.batch, self.state.step);
+        Ok(fit(self, input, truth, validation.map(|v| (v.inputs.as_slice(), v.truths.as_slice()))))
+    }
+}
+
+impl Network for Sequential {
+    /* trainable parameters of every layer, None for layers without parameters */
+    fn get_weights(&self) -> Vec<Option<(Array<f64>, Array<f64>)>> {
+        self.layers.iter().map(|l| l.get_parameters()).collect()
+    }
+
+    fn set_weights(&mut self, weights: Vec<Option<(Array<f64>, Array<f64>)>>) {
+        assert!(weights.len() == self.layers.len(), "[Model] weights do not match the layers.");
+        for (l, w) in self.layers.iter_mut().zip(weights) {
+            if let Some((weights, bias)) = w {
+                l.set_parameters(weights, bias);
+            }
+        }
+    }
+
+    /* non-trainable state of every layer, empty for stateless layers */
+    fn get_states(&self) -> Vec<Vec<Array<f64>>> {
+        self.layers.iter().map(|l| l.get_state()).collect()
+    }
+
+    fn set_states(&mut self, states: Vec<Vec<Array<f64>>>) {
+        assert!(states.len() == self.layers.len(), "[Model] states do not match the layers.");
+        for (l, s) in self.layers.iter_mut().zip(states) {
+            if !s.is_empty() {
+                l.set_state(s);
+            }
+        }
+    }
+
+    fn train_state(&self) -> &TrainState {
+        &self.state
+    }
+
+    fn train_state_mut(&mut self) -> &mut TrainState {
+        &mut self.state
+    }
+
+    fn stop_training(&mut self) {
+        self.stop_training = true;
+    }
+}
+
+impl Trainer for Sequential {
+    type Sample = Vec<f64>;
+
+    fn layers_mut(&mut self) -> Vec<&mut (dyn Layer + 'static)> {
+        self.layers.iter_mut().map(|l| l.as_mut()).collect()
+    }
+
+    fn callbacks_mut(&mut self) -> &mut Vec<Box<dyn Callback>> {
+        &mut self.callbacks
+    }
+
+    fn stop_requested(&mut self) -> &mut bool {
+        &mut self.stop_training
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    fn truncation(&self) -> Option<usize> {
+        self.truncated_bptt
+    }
+
+    fn regularization_loss(&self) -> f64 {
+        Sequential::regularization_loss(self)
     }
-}
\ No newline at end of file
+
+    fn train_batch(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Vec<f64>>) -> f64 {
+        Sequential::train_batch(self, input, truth, batch, learning_rate, predictions)
+    }
+
+    fn evaluate(&mut self, input: &[Vec<f64>], truth: &[Vec<f64>]) -> Evaluation {
+        Sequential::evaluate(self, input, truth)
+    }
+
+    fn metric_values(&self, truth: &[Vec<f64>], predict: &[Vec<f64>]) -> Vec<(String, f64)> {
+        self.metrics.iter().map(|m| (m.name(), m.calculate(truth, predict))).collect()
+    }
+}
+
+/* what callbacks and checkpoint files see of a model, implemented by Sequential and the graph Model */
+pub trait Network {
+    /* trainable parameters of every layer (or node), None where there are none */
+    fn get_weights(&self) -> Vec<Option<(Array<f64>, Array<f64>)>>;
+    fn set_weights(&mut self, weights: Vec<Option<(Array<f64>, Array<f64>)>>);
+    /* non-trainable state of every layer (or node), empty for stateless ones */
+    fn get_states(&self) -> Vec<Vec<Array<f64>>>;
+    fn set_states(&mut self, states: Vec<Vec<Array<f64>>>);
+    fn train_state(&self) -> &TrainState;
+    fn train_state_mut(&mut self) -> &mut TrainState;
+    /* end training after the current batch */
+    fn stop_training(&mut self);
+
+    /* text file of the weights only */
+    fn save_weights(&self, file_name: &str) -> Result<(), io::Error> {
+        let mut content = String::new();
+        write_weights(self, &mut content);
+        fs::write(file_name, content)
+    }
+
+    /* load weights written by save_weights() or save_checkpoint() into a compiled model of the same architecture */
+    fn load_weights(&mut self, file_name: &str) -> Result<(), io::Error> {
+        let content = fs::read_to_string(file_name)?;
+        let mut lines = content.lines().peekable();
+        TrainState::read(&mut lines)?;
+        read_weights(self, &mut lines)
+    }
+
+    /* training state lines followed by the weights */
+    fn save_checkpoint(&self, file_name: &str) -> Result<(), io::Error> {
+        let mut content = String::new();
+        self.train_state().write(&mut content);
+        write_weights(self, &mut content);
+        fs::write(file_name, content)
+    }
+
+    fn load_checkpoint(&mut self, file_name: &str) -> Result<(), io::Error> {
+        let content = fs::read_to_string(file_name)?;
+        let mut lines = content.lines().peekable();
+        let state = TrainState::read(&mut lines)?
+            .ok_or_else(|| invalid_data("no training state in checkpoint"))?;
+        *self.train_state_mut() = state;
+        read_weights(self, &mut lines)
+    }
+}
+
+/* for each layer with parameters: "layer <index>" then shape and data lines of weights and bias,
+ * for each layer with state: "state <index> <count>" then shape and data lines of each array
+ */
+fn write_weights<N: Network + ?Sized>(model: &N, content: &mut String) {
+    for (i, w) in model.get_weights().iter().enumerate() {
+        if let Some((weights, bias)) = w {
+            *content += &format!("layer {}\n", i);
+            write_array(content, weights);
+            write_array(content, bias);
+        }
+    }
+    for (i, s) in model.get_states().iter().enumerate() {
+        if !s.is_empty() {
+            *content += &format!("state {} {}\n", i, s.len());
+            s.iter().for_each(|a| write_array(content, a));
+        }
+    }
+}
+
+fn read_weights<'a, N, I>(model: &mut N, lines: &mut Peekable<I>) -> Result<(), io::Error>
+where
+    N: Network + ?Sized,
+    I: Iterator<Item = &'a str>,
+{
+    let len = model.get_weights().len();
+    let mut weights: Vec<Option<(Array<f64>, Array<f64>)>> = vec![None; len];
+    let mut states: Vec<Vec<Array<f64>>> = vec![Vec::new(); len];
+    while let Some(line) = lines.next() {
+        if let Some(header) = line.strip_prefix("state ") {
+            let (index, count) = header.split_once(' ')
+                .and_then(|(i, n)| Some((i.parse::<usize>().ok()?, n.parse::<usize>().ok()?)))
+                .filter(|&(i, _)| i < len)
+                .ok_or_else(|| invalid_data("bad state header"))?;
+            states[index] = (0..count).map(|_| read_array(lines)).collect::<Result<_, _>>()?;
+            continue;
+        }
+        let index = line.strip_prefix("layer ")
+            .and_then(|i| i.parse::<usize>().ok())
+            .filter(|&i| i < len)
+            .ok_or_else(|| invalid_data("bad layer header"))?;
+        let w = read_array(lines)?;
+        let b = read_array(lines)?;
+        weights[index] = Some((w, b));
+    }
+    model.set_weights(weights);
+    model.set_states(states);
+    Ok(())
+}
+
+/* what the training loop shared by Sequential and the graph Model needs from them */
+pub(crate) trait Trainer: Network {
+    /* one sample, or the truth of one */
+    type Sample: Clone;
+
+    fn layers_mut(&mut self) -> Vec<&mut (dyn Layer + 'static)>;
+    fn callbacks_mut(&mut self) -> &mut Vec<Box<dyn Callback>>;
+    fn stop_requested(&mut self) -> &mut bool;
+    fn seed(&self) -> u64;
+    fn shuffle(&self) -> bool;
+    fn truncation(&self) -> Option<usize>;
+    fn regularization_loss(&self) -> f64;
+    /* forward and backward the samples at `batch`, apply the gradients and return the summed error,
+     * the outputs are pushed to `predictions` when the model has metrics
+     */
+    fn train_batch(&mut self, input: &[Self::Sample], truth: &[Self::Sample], batch: &[usize], learning_rate: f64,
+        predictions: &mut Vec<Self::Sample>) -> f64;
+    fn evaluate(&mut self, input: &[Self::Sample], truth: &[Self::Sample]) -> Evaluation;
+    fn metric_values(&self, truth: &[Self::Sample], predict: &[Self::Sample]) -> Vec<(String, f64)>;
+
+    fn set_training(&mut self, training: bool) {
+        self.layers_mut().into_iter().for_each(|l| l.set_training(training));
+    }
+}
+
+/* validation inputs and truths */
+pub(crate) type Validation<'a, S> = Option<(&'a [S], &'a [S])>;
+
+/* run the epochs left in the training state of the model, `validation` is evaluated after every epoch */
+pub(crate) fn fit<M: Trainer>(model: &mut M, input: &[M::Sample], truth: &[M::Sample], validation: Validation<M::Sample>)
+    -> History {
+    let sample_len = input.len();
+    let TrainState { epoches, batch_size, learning_rate, .. } = *model.train_state();
+
+    assert!(sample_len > 0 && truth.len() == sample_len && batch_size > 0 && learning_rate > 0.0);
+
+    // callbacks get the model itself, so they are moved out while training
+    let mut callbacks = take(model.callbacks_mut());
+    *model.stop_requested() = false;
+    callbacks.iter_mut().for_each(|c| c.on_train_begin(model));
+    // a resumed run continues with the callback states of the checkpoint
+    let saved = take(&mut model.train_state_mut().callbacks);
+    if !saved.is_empty() {
+        assert!(saved.len() == callbacks.len(), "[Model] checkpoint has states of {} callbacks, model has {}.", saved.len(), callbacks.len());
+        callbacks.iter_mut().zip(saved).for_each(|(c, s)| c.set_state(s));
+    }
+
+    let truncation = model.truncation();
+    model.layers_mut().into_iter().for_each(|l| l.set_truncation(truncation));
+
+    let mut history = History::new();
+    for epoch in model.train_state().epoch..epoches {
+        let timer = Instant::now();
+        callbacks.iter_mut().for_each(|c| c.on_epoch_begin(model, epoch + 1));
+
+        // every epoch has its own stream, so a resumed run draws the same numbers
+        let epoch_seed = derive_seed(model.seed(), epoch as u64 + 1);
+        let order = if model.shuffle() {
+            Array::<f64>::permutation(sample_len, &mut stream_rng(epoch_seed, 0))
+        } else {
+            (0..sample_len).collect()
+        };
+        model.set_training(true);
+
+        // batches of this epoch and their summed error from before a mid-epoch stop
+        let done_batches = model.train_state().batch;
+        let first = (done_batches * batch_size).min(sample_len);
+        let mut err = model.train_state().error; // error on all samples
+        let mut seen = first;
+        let mut predictions: Vec<M::Sample> = Vec::new(); // outputs while training, for the metrics
+        let mut completed = true;
+        for (batch, start) in (0..sample_len).step_by(batch_size).enumerate().skip(done_batches) {
+            let end = (start + batch_size).min(sample_len);
+            callbacks.iter_mut().for_each(|c| c.on_batch_begin(model, batch));
+
+            // the layers with random draws are reseeded from a stream of the batch
+            let mut rng = stream_rng(epoch_seed, batch as u64 + 1);
+            for l in model.layers_mut() {
+                l.reseed(rng.gen());
+            }
+            let batch_err = model.train_batch(input, truth, &order[start..end], learning_rate, &mut predictions);
+            err += batch_err;
+            seen = end;
+            let state = model.train_state_mut();
+            state.batch = batch + 1;
+            state.step += 1;
+            state.error = err;
+
+            let batch_loss = batch_err / (end - start) as f64 + model.regularization_loss();
+            callbacks.iter_mut().for_each(|c| c.on_batch_end(model, batch, batch_loss));
+            if *model.stop_requested() && end < sample_len {
+                completed = false;
+                break;
+            }
+        }
+        if !completed {
+            // a checkpoint written after the stop resumes with the callbacks as they are now
+            model.train_state_mut().callbacks = callbacks.iter().map(|c| c.get_state()).collect();
+        } else {
+            let state = model.train_state_mut();
+            (state.epoch, state.batch, state.error) = (epoch + 1, 0, 0.0);
+        }
+
+        err = err / seen as f64 + model.regularization_loss();
+        print!("epoch {}/{}, error: {:.6}", epoch + 1, epoches, err);
+        let metrics: Vec<(String, f64)> = if predictions.is_empty() {
+            Vec::new()
+        } else {
+            let trained: Vec<M::Sample> = order[first..seen].iter().map(|&i| truth[i].clone()).collect();
+            model.metric_values(&trained, &predictions)
+        };
+        for (name, value) in metrics.iter() {
+            print!(", {}: {:.4}", name, value);
+        }
+        let mut record = EpochRecord {
+            epoch: epoch + 1,
+            loss: err,
+            metrics,
+            val_loss: None,
+            val_metrics: Vec::new(),
+            learning_rate,
+            time: timer.elapsed().as_secs_f64(),
+        };
+        if let Some((val_input, val_truth)) = validation {
+            let eval = model.evaluate(val_input, val_truth);
+            print!(", val_error: {:.6}", eval.loss);
+            for (name, value) in eval.metrics.iter() {
+                print!(", val_{}: {:.4}", name, value);
+            }
+            record.val_loss = Some(eval.loss);
+            record.val_metrics = eval.metrics;
+        }
+        println!();
+
+        callbacks.iter_mut().for_each(|c| c.on_epoch_end(model, &record));
+        model.train_state_mut().callbacks = callbacks.iter().map(|c| c.get_state()).collect();
+        callbacks.iter_mut().for_each(|c| c.on_checkpoint(model, &record));
+        history.push(record);
+        if *model.stop_requested() {
+            break;
+        }
+    }
+
+    model.set_training(false);
+    callbacks.iter_mut().for_each(|c| c.on_train_end(model));
+    *model.callbacks_mut() = callbacks;
+    history
+}
+
+/* progress of the current training run, saved with checkpoints so it can be resumed */
+#[derive(Clone, Debug, Default)]
+pub struct TrainState {
+    pub epoch: usize,       // completed epochs
+    pub batch: usize,       // completed batches of the next epoch, when training stopped within it
+    pub step: usize,        // completed batches over all epochs
+    pub epoches: usize,
+    pub batch_size: usize,
+    pub learning_rate: f64, // the whole state of the plain SGD update
+    pub seed: u64,          // the random state, epochs and batches draw from streams derived from it
+    pub error: f64,         // summed error of the completed batches of the next epoch
+    pub callbacks: Vec<Vec<Array<f64>>>, // state of every callback, captured at epoch ends and when training stops
+}
+
+impl TrainState {
+    fn write(&self, content: &mut String) {
+        *content += &format!("epoch {}\n", self.epoch);
+        *content += &format!("batch {}\n", self.batch);
+        *content += &format!("step {}\n", self.step);
+        *content += &format!("epoches {}\n", self.epoches);
+        *content += &format!("batch_size {}\n", self.batch_size);
+        *content += &format!("learning_rate {}\n", self.learning_rate);
+        *content += &format!("seed {}\n", self.seed);
+        *content += &format!("error {}\n", self.error);
+        for (i, state) in self.callbacks.iter().enumerate() {
+            *content += &format!("callback {} {}\n", i, state.len());
+            state.iter().for_each(|a| write_array(content, a));
+        }
+    }
+
+    /* key value lines up to the first layer or state, None for a weights-only file */
+    fn read<'a, I>(lines: &mut Peekable<I>) -> Result<Option<TrainState>, io::Error>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut state = TrainState::default();
+        let mut found = false;
+        while let Some(line) = lines.next_if(|l| !l.starts_with("layer ") && !l.starts_with("state ")) {
+            let (key, value) = line.split_once(' ').ok_or_else(|| invalid_data("bad state line"))?;
+            let bad_value = |_| invalid_data("bad state value");
+            match key {
+                "epoch" => state.epoch = value.parse().map_err(bad_value)?,
+                "batch" => state.batch = value.parse().map_err(bad_value)?,
+                "step" => state.step = value.parse().map_err(bad_value)?,
+                "epoches" => state.epoches = value.parse().map_err(bad_value)?,
+                "batch_size" => state.batch_size = value.parse().map_err(bad_value)?,
+                "learning_rate" => state.learning_rate = value.parse().map_err(|_| invalid_data("bad state value"))?,
+                "seed" => state.seed = value.parse().map_err(bad_value)?,
+                "error" => state.error = value.parse().map_err(|_| invalid_data("bad state value"))?,
+                "callback" => {
+                    let count = value.split_once(' ')
+                        .filter(|(i, _)| i.parse::<usize>().ok() == Some(state.callbacks.len()))
+                        .and_then(|(_, n)| n.parse::<usize>().ok())
+                        .ok_or_else(|| invalid_data("bad callback header"))?;
+                    let arrays = (0..count).map(|_| read_array(lines)).collect::<Result<_, _>>()?;
+                    state.callbacks.push(arrays);
+                }
+                _ => return Err(invalid_data("unknown state key")),
+            }
+            found = true;
+        }
+        Ok(if found { Some(state) } else { None })
+    }
+}
+
+/* shape line followed by data line, f64 Display round-trips exactly */
+pub fn write_array(content: &mut String, array: &Array<f64>) {
+    let shape: Vec<String> = array.shape.iter().map(|s| s.to_string()).collect();
+    let data: Vec<String> = array.data.iter().map(|d| d.to_string()).collect();
+    *content += &(shape.join(",") + "\n");
+    *content += &(data.join(",") + "\n");
+}
+
+pub fn read_array<'a, I>(lines: &mut I) -> Result<Array<f64>, io::Error>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let line = lines.next().ok_or_else(|| invalid_data("missing shape"))?;
+    if line.is_empty() {
+        // the empty array, e.g. the bias of a layer without one
+        lines.next().filter(|l| l.is_empty()).ok_or_else(|| invalid_data("data of empty array"))?;
+        return Ok(Array::<f64>::empty());
+    }
+    let shape: Vec<usize> = line
+        .split(',').map(|s| s.parse::<usize>()).collect::<Result<_, _>>()
+        .map_err(|_| invalid_data("bad shape"))?;
+    let line = lines.next().ok_or_else(|| invalid_data("missing data"))?;
+    let data: Vec<f64> = if line.is_empty() {
+        Vec::new()
+    } else {
+        line.split(',').map(|s| s.parse::<f64>()).collect::<Result<_, _>>()
+            .map_err(|_| invalid_data("bad data"))?
+    };
+    if shape.iter().product::<usize>() != data.len() {
+        return Err(invalid_data("shape and data length not match"));
+    }
+    Ok(Array::<f64>::with(&shape, &data))
+}
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::utils::{layer::{InputLayer, DenseLayer, DropoutLayer}, ops::TanH, callback::{EarlyStopping, Mode}};
+
+    fn train_run(seed: u64) -> (Vec<f64>, Vec<f64>) {
+        let input: Vec<Vec<f64>> = (0..10).map(|i| vec![(i as f64 * 0.5).sin(), i as f64 / 10.0]).collect();
+        let truth: Vec<Vec<f64>> = input.iter().map(|x| vec![x[0] * x[1]]).collect();
+
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 2]))
+            .add(DenseLayer::with_activation(6, TanH))
+            .add(DropoutLayer::new(0.3))
+            .add(DenseLayer::new(1));
+        model.set_shuffle(true);
+        model.compile_with_seed(seed);
+        let history = model.train(&input, &truth, 3, 4, 0.1, None);
+        let parameters = model.get_weights().into_iter().flatten().flat_map(|(w, b)| [w.into_vec(), b.into_vec()].concat()).collect();
+        (history.epochs.iter().map(|r| r.loss).collect(), parameters)
+    }
+
+    #[test]
+    fn same_seed_same_training_run() {
+        // shuffling and dropout draw from the seed, so the runs match bit for bit
+        assert_eq!(train_run(42), train_run(42));
+        assert_ne!(train_run(42), train_run(43));
+    }
+
+    /* requests a stop once the step counter reaches the given step */
+    struct StopAt(Option<usize>);
+
+    impl Callback for StopAt {
+        fn on_batch_end(&mut self, model: &mut dyn Network, _batch: usize, _loss: f64) {
+            if Some(model.train_state().step) == self.0 {
+                model.stop_training();
+            }
+        }
+    }
+
+    fn resumable_model(seed: u64, stop_at: Option<usize>) -> Sequential {
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 2]))
+            .add(DenseLayer::with_activation(6, TanH))
+            .add(DropoutLayer::new(0.3))
+            .add(DenseLayer::new(1));
+        // improves only on the first epoch, so it stops at the end of the third
+        model.add_callback(EarlyStopping::new("loss", Mode::Min, 2).min_delta(1e9)).add_callback(StopAt(stop_at));
+        model.set_shuffle(true);
+        model.compile_with_seed(seed);
+        model
+    }
+
+    #[test]
+    fn resume_mid_epoch_matches_the_uninterrupted_run() {
+        let input: Vec<Vec<f64>> = (0..10).map(|i| vec![(i as f64 * 0.5).sin(), i as f64 / 10.0]).collect();
+        let truth: Vec<Vec<f64>> = input.iter().map(|x| vec![x[0] * x[1]]).collect();
+        let parameters = |model: &Sequential| -> Vec<f64> {
+            model.get_weights().into_iter().flatten().flat_map(|(w, b)| [w.into_vec(), b.into_vec()].concat()).collect()
+        };
+
+        let mut full = resumable_model(42, None);
+        let full_history = full.train(&input, &truth, 6, 4, 0.1, None);
+        assert_eq!(full_history.len(), 3);
+
+        // 3 batches per epoch, step 8 is the second batch of the third epoch
+        let mut first = resumable_model(42, Some(8));
+        let first_history = first.train(&input, &truth, 6, 4, 0.1, None);
+        assert_eq!((first.state.epoch, first.state.batch, first_history.len()), (2, 2, 3));
+        let file_name = env::temp_dir().join(format!("rust_nn_resume_{}.txt", process::id()));
+        let file_name = file_name.to_str().unwrap();
+        first.save_checkpoint(file_name).unwrap();
+
+        let mut resumed = resumable_model(7, None);
+        let resumed_history = resumed.resume(file_name, &input, &truth, None).unwrap();
+        fs::remove_file(file_name).unwrap();
+
+        assert_eq!([&first_history.loss()[..2], &resumed_history.loss()[..]].concat(), full_history.loss());
+        assert_eq!(parameters(&resumed), parameters(&full));
+        assert_eq!(resumed.state.step, full.state.step);
+    }
+}
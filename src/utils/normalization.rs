@@ -0,0 +1,428 @@
+use super::{layer::{Layer, check_batch_input_shape}, shape::Array};
+
+/* batch normalization over the last axis: [batch, n] normalizes each of the n features over the batch,
+ * [rows, cols, ch] each channel over all positions,
+ * gamma / beta are the weights / bias, the running mean / variance are used for inference,
+ * on a [1, n] input Sequential::compile turns on stacked batches, a batch of a single sample (the leftover
+ * of an epoch) is normalized with the running statistics and leaves them unchanged,
+ * convolution inputs are never stacked, so they normalize over the positions of one sample
+ */
+pub struct BatchNormLayer {
+    pub momentum: f64,
+    pub epsilon: f64,
+    pub weights: Array<f64>, // gamma
+    pub bias: Array<f64>,    // beta
+    pub running_mean: Array<f64>,
+    pub running_var: Array<f64>,
+    pub training: bool,
+    pub from_batch: bool, // the last forward pass normalized with the statistics of its batch
+    pub x_hat: Array<f64>,
+    pub std_inv: Vec<f64>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl BatchNormLayer {
+    pub fn new() -> Self {
+        BatchNormLayer {
+            momentum: 0.99,
+            epsilon: 1e-3,
+            weights: Array::<f64>::empty(),
+            bias: Array::<f64>::empty(),
+            running_mean: Array::<f64>::empty(),
+            running_var: Array::<f64>::empty(),
+            training: false,
+            from_batch: false,
+            x_hat: Array::<f64>::empty(),
+            std_inv: Vec::new(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    /* running = momentum * running + (1 - momentum) * batch */
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    fn channels(&self) -> usize {
+        self.weights.data.len()
+    }
+}
+
+impl Default for BatchNormLayer {
+    fn default() -> Self {
+        BatchNormLayer::new()
+    }
+}
+
+impl Layer for BatchNormLayer {
+    fn forward_prop(&mut self, mut input: Array<f64>) -> Array<f64> {
+        check_batch_input_shape("BatchNorm", &self.input_shape, &input.shape);
+
+        let ch = self.channels();
+        let m = input.data.len() / ch;
+        // a single value per channel has no batch variance
+        self.from_batch = self.training && m > 1;
+        let (mean, var) = if self.from_batch {
+            let mut mean = vec![0.0; ch];
+            let mut var = vec![0.0; ch];
+            for (i, x) in input.data.iter().enumerate() {
+                mean[i % ch] += x;
+            }
+            mean.iter_mut().for_each(|v| *v /= m as f64);
+            for (i, x) in input.data.iter().enumerate() {
+                var[i % ch] += (x - mean[i % ch]).powi(2);
+            }
+            var.iter_mut().for_each(|v| *v /= m as f64);
+
+            for c in 0..ch {
+                self.running_mean.data[c] = self.momentum * self.running_mean.data[c] + (1.0 - self.momentum) * mean[c];
+                self.running_var.data[c] = self.momentum * self.running_var.data[c] + (1.0 - self.momentum) * var[c];
+            }
+            (mean, var)
+        } else {
+            (self.running_mean.data.to_vec(), self.running_var.data.to_vec())
+        };
+
+        self.std_inv = var.iter().map(|v| 1.0 / (v + self.epsilon).sqrt()).collect();
+        self.x_hat = input.clone();
+        for i in 0..input.data.len() {
+            let c = i % ch;
+            self.x_hat.data[i] = (input.data[i] - mean[c]) * self.std_inv[c];
+            input.data[i] = self.weights.data[c] * self.x_hat.data[i] + self.bias.data[c];
+        }
+        input
+    }
+
+    fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let ch = self.channels();
+        let m = error.data.len() as f64 / ch as f64;
+
+        let mut weights_error = Array::<f64>::zeros(&self.weights.shape);
+        let mut bias_error = Array::<f64>::zeros(&self.bias.shape);
+        for i in 0..error.data.len() {
+            weights_error.data[i % ch] += error.data[i] * self.x_hat.data[i];
+            bias_error.data[i % ch] += error.data[i];
+        }
+
+        for i in 0..error.data.len() {
+            let c = i % ch;
+            let scale = self.weights.data[c] * self.std_inv[c];
+            error.data[i] = if self.from_batch {
+                // the batch mean and variance depend on every input
+                scale * (error.data[i] - (bias_error.data[c] + self.x_hat.data[i] * weights_error.data[c]) / m)
+            } else {
+                scale * error.data[i]
+            };
+        }
+        (error, Some(weights_error), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        self.weights.add_m(delta_weights);
+        self.bias.add_m(delta_bias);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        if self.weights.shape != weights.shape || self.bias.shape != bias.shape {
+            panic!("[BatchNorm] gamma/beta shape not match.");
+        }
+        self.weights = weights;
+        self.bias = bias;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn get_state(&self) -> Vec<Array<f64>> {
+        vec![self.running_mean.clone(), self.running_var.clone()]
+    }
+
+    fn set_state(&mut self, mut state: Vec<Array<f64>>) {
+        if state.len() != 2 || state[0].shape != self.running_mean.shape || state[1].shape != self.running_var.shape {
+            panic!("[BatchNorm] running statistics shape not match.");
+        }
+        self.running_var = state.pop().unwrap();
+        self.running_mean = state.pop().unwrap();
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        let ch = prev_output_shape[prev_output_shape.len() - 1];
+        self.weights = Array::<f64>::fill(&[1, ch], 1.0);
+        self.bias = Array::<f64>::zeros(&[1, ch]);
+        self.running_mean = Array::<f64>::zeros(&[1, ch]);
+        self.running_var = Array::<f64>::fill(&[1, ch], 1.0);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = prev_output_shape.into();
+        println!("[BatchNorm] config i/o shape: {:?}, channels: {}", self.input_shape, ch);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn batchable(&self) -> bool {
+        true
+    }
+
+    fn batch_statistics(&self) -> bool {
+        self.input_shape.len() == 2 && self.input_shape[0] == 1
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* which values of a sample are normalized together */
+#[derive(Clone, Copy, Debug)]
+enum Grouping {
+    Position,       // the last axis at each position
+    Groups(usize),  // channels split into groups, each over all positions
+    Instance,       // each channel over all positions
+}
+
+/* normalization within each sample, independent of the batch,
+ * a sample is one row of a [1, n] input stacked into [batch, n], the whole array otherwise,
+ * gamma / beta are the weights / bias, one per channel (last axis)
+ */
+macro_rules! new_sample_norm_layer {
+    ($struct:ident, $name:literal) => {
+        pub struct $struct {
+            grouping: Grouping,
+            pub epsilon: f64,
+            pub weights: Array<f64>, // gamma
+            pub bias: Array<f64>,    // beta
+            pub x_hat: Array<f64>,
+            pub std_inv: Vec<f64>,
+            pub input_shape: Box<[usize]>,
+            pub output_shape: Box<[usize]>,
+        }
+
+        impl $struct {
+            fn with_grouping(grouping: Grouping) -> Self {
+                $struct {
+                    grouping,
+                    epsilon: 1e-3,
+                    weights: Array::<f64>::empty(),
+                    bias: Array::<f64>::empty(),
+                    x_hat: Array::<f64>::empty(),
+                    std_inv: Vec::new(),
+                    input_shape: Box::default(),
+                    output_shape: Box::default(),
+                }
+            }
+
+            pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+                self.epsilon = epsilon;
+                self
+            }
+
+            /* normalization set of every value and the number of sets */
+            fn sets(&self, len: usize) -> (Vec<usize>, usize) {
+                let ch = self.weights.data.len();
+                let sample: usize = self.input_shape.iter().product();
+                let groups = match self.grouping {
+                    Grouping::Position => return ((0..len).map(|i| i / ch).collect(), len / ch),
+                    Grouping::Groups(g) => g,
+                    Grouping::Instance => ch,
+                };
+                let set = |i: usize| (i / sample) * groups + (i % ch) / (ch / groups);
+                ((0..len).map(set).collect(), len / sample * groups)
+            }
+        }
+
+        impl Layer for $struct {
+            fn forward_prop(&mut self, mut input: Array<f64>) -> Array<f64> {
+                check_batch_input_shape($name, &self.input_shape, &input.shape);
+
+                let ch = self.weights.data.len();
+                let (set, sets) = self.sets(input.data.len());
+                let mut count = vec![0.0; sets];
+                let mut mean = vec![0.0; sets];
+                let mut var = vec![0.0; sets];
+                for (i, x) in input.data.iter().enumerate() {
+                    count[set[i]] += 1.0;
+                    mean[set[i]] += x;
+                }
+                for s in 0..sets {
+                    mean[s] /= count[s];
+                }
+                for (i, x) in input.data.iter().enumerate() {
+                    var[set[i]] += (x - mean[set[i]]).powi(2);
+                }
+
+                self.std_inv = (0..sets).map(|s| 1.0 / (var[s] / count[s] + self.epsilon).sqrt()).collect();
+                self.x_hat = input.clone();
+                for i in 0..input.data.len() {
+                    self.x_hat.data[i] = (input.data[i] - mean[set[i]]) * self.std_inv[set[i]];
+                    input.data[i] = self.weights.data[i % ch] * self.x_hat.data[i] + self.bias.data[i % ch];
+                }
+                input
+            }
+
+            fn backward_prop(&mut self, mut error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+                let ch = self.weights.data.len();
+                let (set, sets) = self.sets(error.data.len());
+
+                let mut weights_error = Array::<f64>::zeros(&self.weights.shape);
+                let mut bias_error = Array::<f64>::zeros(&self.bias.shape);
+                let mut count = vec![0.0; sets];
+                let mut sum = vec![0.0; sets];     // of d x_hat
+                let mut sum_x = vec![0.0; sets];   // of d x_hat * x_hat
+                for i in 0..error.data.len() {
+                    weights_error.data[i % ch] += error.data[i] * self.x_hat.data[i];
+                    bias_error.data[i % ch] += error.data[i];
+                    error.data[i] *= self.weights.data[i % ch];
+                    count[set[i]] += 1.0;
+                    sum[set[i]] += error.data[i];
+                    sum_x[set[i]] += error.data[i] * self.x_hat.data[i];
+                }
+                for i in 0..error.data.len() {
+                    let s = set[i];
+                    error.data[i] = self.std_inv[s] * (error.data[i] - (sum[s] + self.x_hat.data[i] * sum_x[s]) / count[s]);
+                }
+                (error, Some(weights_error), Some(bias_error))
+            }
+
+            fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+                self.weights.add_m(delta_weights);
+                self.bias.add_m(delta_bias);
+            }
+
+            fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+                if self.weights.shape != weights.shape || self.bias.shape != bias.shape {
+                    panic!("[{}] gamma/beta shape not match.", $name);
+                }
+                self.weights = weights;
+                self.bias = bias;
+            }
+
+            fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+                Some((self.weights.clone(), self.bias.clone()))
+            }
+
+            fn config_shape(&mut self, prev_output_shape: &[usize]) {
+                let ch = prev_output_shape[prev_output_shape.len() - 1];
+                if let Grouping::Groups(g) = self.grouping {
+                    if g == 0 || ch % g != 0 {
+                        panic!("[{}] {} channels can not be split into {} groups.", $name, ch, g);
+                    }
+                }
+                self.weights = Array::<f64>::fill(&[1, ch], 1.0);
+                self.bias = Array::<f64>::zeros(&[1, ch]);
+                self.input_shape = prev_output_shape.into();
+                self.output_shape = prev_output_shape.into();
+                println!("[{}] config i/o shape: {:?}, {:?}", $name, self.input_shape, self.grouping);
+            }
+
+            fn batchable(&self) -> bool {
+                true
+            }
+
+            fn get_output_shape(&self) -> &[usize] {
+                &self.output_shape
+            }
+        }
+    };
+}
+
+new_sample_norm_layer!(LayerNormLayer, "LayerNorm");
+new_sample_norm_layer!(GroupNormLayer, "GroupNorm");
+new_sample_norm_layer!(InstanceNormLayer, "InstanceNorm");
+
+impl LayerNormLayer {
+    pub fn new() -> Self {
+        LayerNormLayer::with_grouping(Grouping::Position)
+    }
+}
+
+impl Default for LayerNormLayer {
+    fn default() -> Self {
+        LayerNormLayer::new()
+    }
+}
+
+impl GroupNormLayer {
+    /* the channels must be divisible by `groups`, 1 group normalizes each sample as a whole */
+    pub fn new(groups: usize) -> Self {
+        GroupNormLayer::with_grouping(Grouping::Groups(groups))
+    }
+}
+
+/* meant for [rows, cols, ch] inputs, on a [1, n] input every value is its own instance */
+impl InstanceNormLayer {
+    pub fn new() -> Self {
+        InstanceNormLayer::with_grouping(Grouping::Instance)
+    }
+}
+
+impl Default for InstanceNormLayer {
+    fn default() -> Self {
+        InstanceNormLayer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{gradient_check::check_layer, model::Sequential, layer::{InputLayer, DenseLayer}};
+
+    #[test]
+    fn batch_norm_gradients() {
+        check_layer(&mut BatchNormLayer::new(), &[4, 3], true);
+    }
+
+    #[test]
+    fn batch_norm_trains_with_a_leftover_sample() {
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 3]))
+            .add(DenseLayer::new(4))
+            .add(BatchNormLayer::new())
+            .add(DenseLayer::new(1));
+        model.compile_with_seed(3);
+        assert!(model.stacked_batches);
+
+        // 5 samples in batches of 2 leave a batch of a single sample every epoch
+        let input: Vec<Vec<f64>> = (0..5).map(|i| vec![i as f64, 1.0 - i as f64, 0.5 * i as f64]).collect();
+        let truth: Vec<Vec<f64>> = (0..5).map(|i| vec![i as f64 * 0.1]).collect();
+        let initial = model.layers[2].get_state();
+        let history = model.train(&input, &truth, 3, 2, 0.01, None);
+        assert!(history.epochs.iter().all(|r| r.loss.is_finite()));
+        assert_ne!(model.layers[2].get_state()[0].data, initial[0].data);
+
+        // a single sample normalizes with the running statistics, the same as inference, and keeps them
+        let sample = Array::<f64>::with(&[1, 4], &[0.3, -0.2, 0.9, 0.1]);
+        let layer = &mut model.layers[2];
+        let running = layer.get_state();
+        layer.set_training(false);
+        let inference = layer.forward_prop(sample.clone());
+        layer.set_training(true);
+        assert_eq!(layer.forward_prop(sample).data, inference.data);
+        assert_eq!(layer.get_state()[1].data, running[1].data);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch size of 2 or more")]
+    fn batch_norm_rejects_single_sample_batches() {
+        let mut model = Sequential::new();
+        model.add(InputLayer::new(&[1, 2])).add(BatchNormLayer::new());
+        model.compile();
+        model.train(&[vec![1.0, 2.0]], &[vec![0.0, 1.0]], 1, 1, 0.01, None);
+    }
+
+    #[test]
+    fn sample_norm_gradients() {
+        check_layer(&mut LayerNormLayer::new(), &[3, 4], true);
+        check_layer(&mut GroupNormLayer::new(2), &[3, 3, 4], true);
+        check_layer(&mut InstanceNormLayer::new(), &[3, 3, 2], true);
+    }
+}
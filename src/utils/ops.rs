@@ -1,69 +1,448 @@
-use std::{f64::consts::E};
+use std::f64::consts::{E, PI, SQRT_2};
 
-use super::shape::Array;
-pub trait Operator {
-    fn activation(x: f64) -> f64;
-    fn derivative(x: f64) -> f64;
+/* element-wise activation function, parameters such as a slope or a bound are fields of the implementor */
+pub trait Activation {
+    fn name(&self) -> String;
+    fn activation(&self, x: f64) -> f64;
+    /* derivative at the input x */
+    fn derivative(&self, x: f64) -> f64;
+    /* whether the derivative is determined by the output, then backward only needs to keep the output */
+    fn has_output_derivative(&self) -> bool { false }
+    /* derivative at the input x with y = activation(x), only called if has_output_derivative() */
+    fn derivative_from_output(&self, _y: f64) -> f64 {
+        panic!("[Activ] {} has no derivative from the output.", self.name())
+    }
 }
+
 #[derive(Default)]
 pub struct Sigmoid;
 
 #[derive(Default)]
 pub struct ReLU;
 
-#[derive(Default)]
-pub struct ReLU6;
+/* ReLU bounded by `max`, ReLU6 for max = 6 */
+pub struct ClippedReLU {
+    pub max: f64,
+}
 
 pub struct TanH;
 
-impl Operator for Sigmoid {
-    fn activation(x: f64) -> f64 {
+/* x for x > 0, alpha * x otherwise */
+pub struct LeakyReLU {
+    pub alpha: f64,
+}
+
+/* x for x > 0, alpha * (e^x - 1) otherwise */
+#[allow(clippy::upper_case_acronyms)]
+pub struct ELU {
+    pub alpha: f64,
+}
+
+/* scaled ELU with the self-normalizing constants */
+#[allow(clippy::upper_case_acronyms)]
+pub struct SELU;
+
+/* x * Φ(x), Φ the standard normal CDF */
+#[allow(clippy::upper_case_acronyms)]
+pub struct GELU;
+
+/* tanh approximation of GELU */
+pub struct GELUTanh;
+
+/* x * sigmoid(x), also called SiLU */
+pub struct Swish;
+
+/* x * tanh(softplus(x)) */
+pub struct Mish;
+
+/* ln(1 + e^x) */
+pub struct Softplus;
+
+/* piecewise linear sigmoid, relu6(x + 3) / 6 */
+pub struct HardSigmoid;
+
+/* x * relu6(x + 3) / 6 */
+pub struct HardSwish;
+
+pub const SELU_ALPHA: f64 = 1.6732632423543772;
+pub const SELU_SCALE: f64 = 1.0507009873554805;
+
+impl Activation for Sigmoid {
+    fn name(&self) -> String {
+        "sigmoid".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
         1.0 / (1.0 + E.powf(-x))
     }
-    fn derivative(x: f64) -> f64 {
-        let sx = Self::activation(x);
+    fn derivative(&self, x: f64) -> f64 {
+        let sx = self.activation(x);
         sx * (1.0 - sx)
     }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        y * (1.0 - y)
+    }
 }
 
-impl Operator for ReLU {
-    fn activation(x: f64) -> f64 {
+impl Activation for ReLU {
+    fn name(&self) -> String {
+        "relu".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
         x.max(0.0)
     }
-    fn derivative(x: f64) -> f64 {
+    fn derivative(&self, x: f64) -> f64 {
         if x > 0.0 {
             1.0
         } else {
             0.0
         }
     }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 { 1.0 } else { 0.0 }
+    }
 }
 
-impl Operator for ReLU6 {
-    fn activation(x: f64) -> f64 {
-        x.max(0.0).min(6.0)
+impl Activation for ClippedReLU {
+    fn name(&self) -> String {
+        if self.max == 6.0 { "relu6".to_string() } else { format!("clipped_relu({})", self.max) }
+    }
+    fn activation(&self, x: f64) -> f64 {
+        x.clamp(0.0, self.max)
     }
-    fn derivative(x: f64) -> f64 {
-        if x > 0.0 && x < 6.0 {
+    fn derivative(&self, x: f64) -> f64 {
+        if x > 0.0 && x < self.max {
             1.0
         } else {
             0.0
         }
     }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 && y < self.max { 1.0 } else { 0.0 }
+    }
 }
 
-impl Operator for TanH {
-    fn activation(x: f64) -> f64 {
+impl Activation for TanH {
+    fn name(&self) -> String {
+        "tanh".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
         x.tanh()
     }
-    fn derivative(x: f64) -> f64 {
+    fn derivative(&self, x: f64) -> f64 {
         1.0 - x.tanh().powi(2)
     }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        1.0 - y * y
+    }
+}
+
+impl Activation for LeakyReLU {
+    fn name(&self) -> String {
+        format!("leaky_relu({})", self.alpha)
+    }
+    fn activation(&self, x: f64) -> f64 {
+        if x > 0.0 { x } else { self.alpha * x }
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        if x > 0.0 { 1.0 } else { self.alpha }
+    }
+    fn has_output_derivative(&self) -> bool {
+        // with a negative slope a positive output can come from either side of 0
+        self.alpha >= 0.0
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 { 1.0 } else { self.alpha }
+    }
+}
+
+impl Activation for ELU {
+    fn name(&self) -> String {
+        format!("elu({})", self.alpha)
+    }
+    fn activation(&self, x: f64) -> f64 {
+        if x > 0.0 { x } else { self.alpha * x.exp_m1() }
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        if x > 0.0 { 1.0 } else { self.alpha * x.exp() }
+    }
+    fn has_output_derivative(&self) -> bool {
+        // with alpha <= 0 a non-positive output can come from either side of 0
+        self.alpha > 0.0
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 { 1.0 } else { y + self.alpha }
+    }
+}
+
+impl Activation for SELU {
+    fn name(&self) -> String {
+        "selu".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        SELU_SCALE * ELU { alpha: SELU_ALPHA }.activation(x)
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        SELU_SCALE * ELU { alpha: SELU_ALPHA }.derivative(x)
+    }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 { SELU_SCALE } else { y + SELU_SCALE * SELU_ALPHA }
+    }
+}
+
+impl Activation for GELU {
+    fn name(&self) -> String {
+        "gelu".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        x * normal_cdf(x)
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        normal_cdf(x) + x * (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+    }
+}
+
+const GELU_C: f64 = 0.044715;
+
+impl Activation for GELUTanh {
+    fn name(&self) -> String {
+        "gelu_tanh".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        let t = ((2.0 / PI).sqrt() * (x + GELU_C * x.powi(3))).tanh();
+        0.5 * x * (1.0 + t)
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        let t = ((2.0 / PI).sqrt() * (x + GELU_C * x.powi(3))).tanh();
+        0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * (2.0 / PI).sqrt() * (1.0 + 3.0 * GELU_C * x * x)
+    }
+}
+
+impl Activation for Swish {
+    fn name(&self) -> String {
+        "swish".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        x * Sigmoid.activation(x)
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        let s = Sigmoid.activation(x);
+        s + x * s * (1.0 - s)
+    }
+}
+
+impl Activation for Mish {
+    fn name(&self) -> String {
+        "mish".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        x * Softplus.activation(x).tanh()
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        let t = Softplus.activation(x).tanh();
+        t + x * (1.0 - t * t) * Sigmoid.activation(x)
+    }
+}
+
+impl Activation for Softplus {
+    fn name(&self) -> String {
+        "softplus".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        // stable for large |x|
+        x.max(0.0) + (-x.abs()).exp().ln_1p()
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        Sigmoid.activation(x)
+    }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        -(-y).exp_m1()
+    }
+}
+
+impl Activation for HardSigmoid {
+    fn name(&self) -> String {
+        "hard_sigmoid".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        ClippedReLU { max: 6.0 }.activation(x + 3.0) / 6.0
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        ClippedReLU { max: 6.0 }.derivative(x + 3.0) / 6.0
+    }
+    fn has_output_derivative(&self) -> bool {
+        true
+    }
+    fn derivative_from_output(&self, y: f64) -> f64 {
+        if y > 0.0 && y < 1.0 { 1.0 / 6.0 } else { 0.0 }
+    }
+}
+
+impl Activation for HardSwish {
+    fn name(&self) -> String {
+        "hard_swish".to_string()
+    }
+    fn activation(&self, x: f64) -> f64 {
+        x * HardSigmoid.activation(x)
+    }
+    fn derivative(&self, x: f64) -> f64 {
+        HardSigmoid.activation(x) + x * HardSigmoid.derivative(x)
+    }
+}
+
+/* activation of a name as printed by Activation::name(), the parameters in parentheses are optional:
+ * "leaky_relu" has slope 0.01, "elu" alpha 1, "clipped_relu" bound 6
+ */
+pub fn activation_by_name(name: &str) -> Option<Box<dyn Activation>> {
+    let (base, param) = match name.split_once('(') {
+        Some((base, rest)) => (base, Some(rest.strip_suffix(')')?.trim().parse::<f64>().ok()?)),
+        None => (name, None),
+    };
+    let activation: Box<dyn Activation> = match (base.trim(), param) {
+        ("sigmoid", None) => Box::new(Sigmoid),
+        ("relu", None) => Box::new(ReLU),
+        ("relu6", None) => Box::new(ClippedReLU { max: 6.0 }),
+        ("clipped_relu", max) => Box::new(ClippedReLU { max: max.unwrap_or(6.0) }),
+        ("tanh", None) => Box::new(TanH),
+        ("leaky_relu", alpha) => Box::new(LeakyReLU { alpha: alpha.unwrap_or(0.01) }),
+        ("elu", alpha) => Box::new(ELU { alpha: alpha.unwrap_or(1.0) }),
+        ("selu", None) => Box::new(SELU),
+        ("gelu", None) => Box::new(GELU),
+        ("gelu_tanh", None) => Box::new(GELUTanh),
+        ("swish" | "silu", None) => Box::new(Swish),
+        ("mish", None) => Box::new(Mish),
+        ("softplus", None) => Box::new(Softplus),
+        ("hard_sigmoid", None) => Box::new(HardSigmoid),
+        ("hard_swish", None) => Box::new(HardSwish),
+        _ => return None,
+    };
+    Some(activation)
+}
+
+/* standard normal CDF, Φ(x) = erfc(-x / √2) / 2 */
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
 }
 
-pub fn calculate(mut input: Array<f64>, op_cal: fn(f64) -> f64) -> Array<f64> {
-    for i in 0..input.sub_size[0] {
-        input.data[i] = op_cal(input.data[i]);
+/* complementary error function to about 1e-15,
+ * Taylor series of erf for |x| < 2.5, continued fraction of erfc beyond
+ */
+pub fn erfc(x: f64) -> f64 {
+    if x.abs() < 2.5 {
+        let mut sum: f64 = 0.0;
+        let mut term = x; // (-1)^n x^(2n+1) / n!
+        let mut n = 0.0;
+        while term.abs() > 1e-17 * sum.abs() {
+            sum += term / (2.0 * n + 1.0);
+            n += 1.0;
+            term *= -x * x / n;
+        }
+        1.0 - 2.0 / PI.sqrt() * sum
+    } else if x > 0.0 {
+        // erfc(x) = e^(-x²) / √π / (x + (1/2) / (x + 1 / (x + (3/2) / (x + ...))))
+        let mut t = x;
+        for k in (1..=60).rev() {
+            t = x + k as f64 / 2.0 / t;
+        }
+        (-x * x).exp() / PI.sqrt() / t
+    } else {
+        2.0 - erfc(-x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{layer::{Layer, ActivationLayer}, shape::Array, gradient_check::check_layer};
+
+    fn all() -> Vec<Box<dyn Activation>> {
+        vec![Box::new(Sigmoid), Box::new(ReLU), Box::new(ClippedReLU { max: 6.0 }), Box::new(ClippedReLU { max: 2.5 }), Box::new(TanH),
+            Box::new(LeakyReLU { alpha: 0.2 }), Box::new(LeakyReLU { alpha: -0.5 }), Box::new(ELU { alpha: 1.5 }), Box::new(SELU),
+            Box::new(GELU), Box::new(GELUTanh), Box::new(Swish), Box::new(Mish), Box::new(Softplus), Box::new(HardSigmoid), Box::new(HardSwish)]
+    }
+
+    // away from the kinks at 0, ±3 and the clipping bounds
+    const POINTS: [f64; 8] = [-7.0, -2.2, -0.9, -0.3, 0.4, 1.3, 2.8, 4.5];
+
+    #[test]
+    fn derivatives_match_central_differences() {
+        for a in all() {
+            for x in POINTS {
+                let numeric = (a.activation(x + 1e-6) - a.activation(x - 1e-6)) / 2e-6;
+                assert!((a.derivative(x) - numeric).abs() < 1e-6, "{} at {}: {} vs {}", a.name(), x, a.derivative(x), numeric);
+                if a.has_output_derivative() {
+                    let from_output = a.derivative_from_output(a.activation(x));
+                    assert!((from_output - a.derivative(x)).abs() < 1e-12, "{} at {} from the output: {}", a.name(), x, from_output);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        assert_eq!(ReLU.activation(-1.0), 0.0);
+        assert_eq!(ClippedReLU { max: 6.0 }.activation(7.0), 6.0);
+        assert_eq!(LeakyReLU { alpha: 0.2 }.activation(-2.0), -0.4);
+        assert_eq!(ELU { alpha: 2.0 }.activation(0.0), 0.0);
+        assert!((SELU.activation(-50.0) + SELU_SCALE * SELU_ALPHA).abs() < 1e-12);
+        assert!((GELU.activation(1.0) - 0.8413447460685429).abs() < 1e-12);
+        assert!((GELUTanh.activation(1.0) - GELU.activation(1.0)).abs() < 1e-3);
+        assert_eq!(Softplus.activation(1000.0), 1000.0);
+        assert_eq!(HardSigmoid.activation(0.0), 0.5);
+        assert_eq!(HardSwish.activation(-4.0), 0.0);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for a in all() {
+            let b = activation_by_name(&a.name()).unwrap();
+            assert_eq!(b.name(), a.name());
+            assert!(POINTS.iter().all(|&x| b.activation(x) == a.activation(x)), "{}", a.name());
+        }
+        // default parameters
+        assert_eq!(activation_by_name("leaky_relu").unwrap().name(), "leaky_relu(0.01)");
+        assert_eq!(activation_by_name("elu").unwrap().name(), "elu(1)");
+        assert_eq!(activation_by_name("clipped_relu").unwrap().name(), "relu6");
+        assert_eq!(activation_by_name("silu").unwrap().name(), "swish");
+        for name in ["softmax", "relu(2)", "elu(a)", "elu(1"] {
+            assert!(activation_by_name(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn activation_layer() {
+        let mut layer = ActivationLayer::from_name("leaky_relu(0.2)");
+        layer.config_shape(&[1, 3]);
+        let output = layer.forward_prop(Array::<f64>::with(&[1, 3], &[-1.0, 0.0, 2.0]));
+        assert_eq!(output.data.to_vec(), vec![-0.2, 0.0, 2.0]);
+        let (error, _, _) = layer.backward_prop(Array::<f64>::fill(&[1, 3], 1.0));
+        assert_eq!(error.data.to_vec(), vec![0.2, 0.2, 1.0]);
+
+        for a in all() {
+            check_layer(&mut ActivationLayer::with_boxed(a), &[2, 3], true);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "[Activ] unknown activation \"softmax\".")]
+    fn activation_layer_of_an_unknown_name_panics() {
+        ActivationLayer::from_name("softmax");
     }
-    input
 }
@@ -0,0 +1,284 @@
+use super::{layer::{Layer, Padding, check_input_shape}, shape::Array};
+
+/* window reduction of a pooling layer */
+#[derive(Clone, Copy, Debug)]
+enum Pool {
+    Max,
+    Average,
+}
+
+/* pooling over pool_size x pool_size windows of [rows, cols, ch] inputs, each channel separately,
+ * cells of the padding are ignored: not a maximum candidate and not counted in the average
+ */
+macro_rules! new_pool2d_layer {
+    ($struct:ident, $pool:expr, $name:literal) => {
+        pub struct $struct {
+            pub pool_size: usize,
+            pub stride: usize,
+            pub padding: Padding,
+            pub pad: (usize, usize),
+            pub routes: Vec<Vec<usize>>, // input indices of the window of every output
+            pub input_shape: Box<[usize]>,
+            pub output_shape: Box<[usize]>,
+        }
+
+        impl $struct {
+            /* stride defaults to the pool size */
+            pub fn new(pool_size: usize) -> Self {
+                $struct {
+                    pool_size,
+                    stride: pool_size,
+                    padding: Padding::Valid,
+                    pad: (0, 0),
+                    routes: Vec::new(),
+                    input_shape: Box::default(),
+                    output_shape: Box::default(),
+                }
+            }
+
+            pub fn with_stride(mut self, stride: usize) -> Self {
+                self.stride = stride;
+                self
+            }
+
+            pub fn with_padding(mut self, padding: Padding) -> Self {
+                self.padding = padding;
+                self
+            }
+
+            /* input indices inside the window of output (i, j, k) */
+            fn window(&self, i: usize, j: usize, k: usize) -> Vec<usize> {
+                let (rows, cols, ch) = (self.input_shape[0], self.input_shape[1], self.input_shape[2]);
+                let mut cells = Vec::with_capacity(self.pool_size * self.pool_size);
+                for pi in 0..self.pool_size {
+                    for pj in 0..self.pool_size {
+                        let r = (i * self.stride + pi).checked_sub(self.pad.0).filter(|&r| r < rows);
+                        let c = (j * self.stride + pj).checked_sub(self.pad.1).filter(|&c| c < cols);
+                        if let (Some(r), Some(c)) = (r, c) {
+                            cells.push((r * cols + c) * ch + k);
+                        }
+                    }
+                }
+                cells
+            }
+        }
+
+        impl Layer for $struct {
+            fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+                check_input_shape($name, &self.input_shape, &input.shape);
+
+                let (o_rows, o_cols, ch) = (self.output_shape[0], self.output_shape[1], self.output_shape[2]);
+                let mut res = Array::<f64>::zeros(&self.output_shape);
+                self.routes = Vec::with_capacity(res.data.len());
+                for i in 0..o_rows {
+                    for j in 0..o_cols {
+                        for k in 0..ch {
+                            let mut cells = self.window(i, j, k);
+                            res[&[i, j, k]] = match $pool {
+                                Pool::Max => {
+                                    // the gradient is routed to the first maximum only
+                                    let max = cells.iter().copied()
+                                        .reduce(|a, b| if input.data[b] > input.data[a] { b } else { a })
+                                        .unwrap();
+                                    cells = vec![max];
+                                    input.data[max]
+                                }
+                                Pool::Average => cells.iter().map(|&c| input.data[c]).sum::<f64>() / cells.len() as f64,
+                            };
+                            self.routes.push(cells);
+                        }
+                    }
+                }
+                res
+            }
+
+            fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+                let mut input_error = Array::<f64>::zeros(&self.input_shape);
+                for (o, cells) in self.routes.iter().enumerate() {
+                    let e = error.data[o] / cells.len() as f64;
+                    for &c in cells.iter() {
+                        input_error.data[c] += e;
+                    }
+                }
+                (input_error, None, None)
+            }
+
+            fn config_shape(&mut self, prev_output_shape: &[usize]) {
+                if prev_output_shape.len() != 3 {
+                    panic!("[{}] input must be [rows, cols, channels].", $name);
+                }
+                let (o_rows, pad_rows) = self.padding.output_size(prev_output_shape[0], self.pool_size, self.stride);
+                let (o_cols, pad_cols) = self.padding.output_size(prev_output_shape[1], self.pool_size, self.stride);
+                self.pad = (pad_rows, pad_cols);
+                self.input_shape = prev_output_shape.into();
+                self.output_shape = Box::new([o_rows, o_cols, prev_output_shape[2]]);
+                println!("[{}] config input shape: {:?}, output shape: {:?}", $name, self.input_shape, self.output_shape);
+            }
+
+            fn get_output_shape(&self) -> &[usize] {
+                &self.output_shape
+            }
+        }
+    };
+}
+
+new_pool2d_layer!(MaxPool2DLayer, Pool::Max, "MaxPool2D");
+new_pool2d_layer!(AvgPool2DLayer, Pool::Average, "AvgPool2D");
+
+/* pooling of every channel over all positions of [rows, cols, ch], the output is [1, ch] for a following DenseLayer */
+macro_rules! new_global_pool2d_layer {
+    ($struct:ident, $pool:expr, $name:literal) => {
+        pub struct $struct {
+            pub argmax: Vec<usize>,
+            pub input_shape: Box<[usize]>,
+            pub output_shape: Box<[usize]>,
+        }
+
+        impl $struct {
+            pub fn new() -> Self {
+                $struct {
+                    argmax: Vec::new(),
+                    input_shape: Box::default(),
+                    output_shape: Box::default(),
+                }
+            }
+        }
+
+        impl Default for $struct {
+            fn default() -> Self {
+                $struct::new()
+            }
+        }
+
+        impl Layer for $struct {
+            fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+                check_input_shape($name, &self.input_shape, &input.shape);
+
+                let ch = self.output_shape[1];
+                let positions = (input.data.len() / ch) as f64;
+                let mut res = Array::<f64>::zeros(&self.output_shape);
+                match $pool {
+                    Pool::Max => {
+                        self.argmax = (0..ch).collect();
+                        for (i, x) in input.data.iter().enumerate() {
+                            if *x > input.data[self.argmax[i % ch]] {
+                                self.argmax[i % ch] = i;
+                            }
+                        }
+                        for k in 0..ch {
+                            res.data[k] = input.data[self.argmax[k]];
+                        }
+                    }
+                    Pool::Average => {
+                        for (i, x) in input.data.iter().enumerate() {
+                            res.data[i % ch] += x / positions;
+                        }
+                    }
+                }
+                res
+            }
+
+            fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+                let ch = self.output_shape[1];
+                let mut input_error = Array::<f64>::zeros(&self.input_shape);
+                let positions = (input_error.data.len() / ch) as f64;
+                match $pool {
+                    Pool::Max => {
+                        for k in 0..ch {
+                            input_error.data[self.argmax[k]] = error.data[k];
+                        }
+                    }
+                    Pool::Average => {
+                        for (i, e) in input_error.data.iter_mut().enumerate() {
+                            *e = error.data[i % ch] / positions;
+                        }
+                    }
+                }
+                (input_error, None, None)
+            }
+
+            fn config_shape(&mut self, prev_output_shape: &[usize]) {
+                if prev_output_shape.len() != 3 {
+                    panic!("[{}] input must be [rows, cols, channels].", $name);
+                }
+                self.input_shape = prev_output_shape.into();
+                self.output_shape = Box::new([1, prev_output_shape[2]]);
+                println!("[{}] config input shape: {:?}, output shape: {:?}", $name, self.input_shape, self.output_shape);
+            }
+
+            fn get_output_shape(&self) -> &[usize] {
+                &self.output_shape
+            }
+        }
+    };
+}
+
+new_global_pool2d_layer!(GlobalMaxPooling2DLayer, Pool::Max, "GlobalMaxPool2D");
+new_global_pool2d_layer!(GlobalAveragePooling2DLayer, Pool::Average, "GlobalAvgPool2D");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    #[test]
+    fn output_shapes() {
+        let shape = |mut layer: MaxPool2DLayer| {
+            layer.config_shape(&[5, 6, 2]);
+            layer.get_output_shape().to_vec()
+        };
+        // a window not dividing the size drops the last row with valid padding and pads it with same padding
+        assert_eq!(shape(MaxPool2DLayer::new(2)), vec![2, 3, 2]);
+        assert_eq!(shape(MaxPool2DLayer::new(2).with_padding(Padding::Same)), vec![3, 3, 2]);
+        assert_eq!(shape(MaxPool2DLayer::new(3).with_stride(1)), vec![3, 4, 2]);
+        assert_eq!(shape(MaxPool2DLayer::new(3).with_stride(2).with_padding(Padding::Same)), vec![3, 3, 2]);
+
+        let mut global = GlobalAveragePooling2DLayer::new();
+        global.config_shape(&[5, 6, 2]);
+        assert_eq!(global.get_output_shape(), &[1, 2]);
+    }
+
+    #[test]
+    fn known_values() {
+        let input = Array::<f64>::with(&[3, 3, 1], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]);
+        let mut max = MaxPool2DLayer::new(2).with_padding(Padding::Same);
+        max.config_shape(&[3, 3, 1]);
+        assert_eq!(max.forward_prop(input.clone()).data.to_vec(), vec![5.0, 6.0, 8.0, 9.0]);
+
+        // the padded cells are not counted in the average
+        let mut average = AvgPool2DLayer::new(2).with_padding(Padding::Same);
+        average.config_shape(&[3, 3, 1]);
+        assert_eq!(average.forward_prop(input.clone()).data.to_vec(), vec![3.0, 4.5, 7.5, 9.0]);
+
+        let mut global = GlobalAveragePooling2DLayer::new();
+        global.config_shape(&[3, 3, 1]);
+        assert_eq!(global.forward_prop(input).data.to_vec(), vec![5.0]);
+    }
+
+    #[test]
+    fn ties_route_the_gradient_to_the_first_maximum() {
+        let input = Array::<f64>::with(&[2, 2, 1], &[1.0, 3.0, 3.0, 3.0]);
+        let mut max = MaxPool2DLayer::new(2);
+        max.config_shape(&[2, 2, 1]);
+        assert_eq!(max.forward_prop(input.clone()).data.to_vec(), vec![3.0]);
+        assert_eq!(max.backward_prop(Array::<f64>::with(&[1, 1, 1], &[2.0])).0.data.to_vec(), vec![0.0, 2.0, 0.0, 0.0]);
+
+        let mut global = GlobalMaxPooling2DLayer::new();
+        global.config_shape(&[2, 2, 1]);
+        assert_eq!(global.forward_prop(input).data.to_vec(), vec![3.0]);
+        assert_eq!(global.backward_prop(Array::<f64>::with(&[1, 1], &[2.0])).0.data.to_vec(), vec![0.0, 2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn pooling_gradients() {
+        check_layer(&mut MaxPool2DLayer::new(2), &[4, 4, 2], true);
+        check_layer(&mut AvgPool2DLayer::new(2), &[4, 4, 2], true);
+        // overlapping windows, and windows not dividing the size
+        check_layer(&mut MaxPool2DLayer::new(3).with_stride(2), &[5, 4, 2], true);
+        check_layer(&mut AvgPool2DLayer::new(3).with_stride(2), &[5, 4, 2], true);
+        check_layer(&mut MaxPool2DLayer::new(2).with_padding(Padding::Same), &[5, 3, 1], true);
+        check_layer(&mut AvgPool2DLayer::new(2).with_stride(1).with_padding(Padding::Same), &[5, 3, 1], true);
+        check_layer(&mut GlobalMaxPooling2DLayer::new(), &[3, 4, 2], true);
+        check_layer(&mut GlobalAveragePooling2DLayer::new(), &[3, 4, 2], true);
+    }
+}
@@ -0,0 +1,13 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+/* independent seed for a numbered stream (epoch, fold, layer, ...) of a base seed, splitmix64 finalizer */
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn stream_rng(seed: u64, stream: u64) -> StdRng {
+    StdRng::seed_from_u64(derive_seed(seed, stream))
+}
\ No newline at end of file
@@ -0,0 +1,581 @@
+use std::ops::Range;
+use rand::rngs::StdRng;
+
+use super::{layer::{Layer, check_input_shape, pack_sublayer_parameters,
+    set_sublayer_parameters, update_sublayer_parameters, pack_sublayer_deltas}, shape::Array, initializer::Initializer,
+    ops::{Activation, Sigmoid, TanH}};
+
+/* one timestep of a recurrent layer,
+ * the kernel is [features + units, gates * units]: rows of the input x then of the previous state h,
+ * columns are grouped by gate, the bias is [1, gates * units]
+ */
+pub trait RecurrentCell {
+    type Cache;
+    fn name(&self) -> String;
+    fn gates(&self) -> usize;
+    /* LSTM carries a cell state c next to h, other cells leave it empty */
+    fn has_cell_state(&self) -> bool { false }
+    fn init_bias(&self, _bias: &mut Array<f64>, _units: usize) {}
+    /* (h, c) of this timestep from the input and the previous (h, c) */
+    fn step(&self, kernel: &Array<f64>, bias: &Array<f64>, x: &[f64], h: &[f64], c: &[f64]) -> (Vec<f64>, Vec<f64>, Self::Cache);
+    /* gradients of one timestep from the gradients of its (h, c): adds to the kernel and bias gradients,
+     * returns the gradients of the input and of the previous (h, c)
+     */
+    fn step_back(&self, kernel: &Array<f64>, cache: &Self::Cache, dh: &[f64], dc: &[f64], dk: &mut Array<f64>, db: &mut Array<f64>)
+        -> (Vec<f64>, Vec<f64>, Vec<f64>);
+}
+
+/* v * kernel[:, cols] + bias[cols] */
+fn affine(kernel: &Array<f64>, bias: &Array<f64>, v: &[f64], cols: Range<usize>) -> Vec<f64> {
+    let width = kernel.shape[1];
+    let mut z: Vec<f64> = bias.data[cols.clone()].to_vec();
+    for (i, x) in v.iter().enumerate() {
+        let row = &kernel.data[i * width..(i + 1) * width];
+        for (zj, k) in z.iter_mut().zip(row[cols.clone()].iter()) {
+            *zj += x * k;
+        }
+    }
+    z
+}
+
+/* gradients of affine(): dz is added to the kernel and bias gradients, returns the gradient of v */
+fn affine_back(kernel: &Array<f64>, v: &[f64], dz: &[f64], cols: Range<usize>, dk: &mut Array<f64>, db: &mut Array<f64>) -> Vec<f64> {
+    let width = kernel.shape[1];
+    for (b, d) in db.data[cols.clone()].iter_mut().zip(dz.iter()) {
+        *b += d;
+    }
+    let mut dv = vec![0.0; v.len()];
+    for (i, x) in v.iter().enumerate() {
+        let row = i * width + cols.start;
+        for (j, d) in dz.iter().enumerate() {
+            dk.data[row + j] += x * d;
+            dv[i] += kernel.data[row + j] * d;
+        }
+    }
+    dv
+}
+
+fn concat(a: &[f64], b: &[f64]) -> Vec<f64> {
+    a.iter().chain(b.iter()).copied().collect()
+}
+
+/* h = activation(x W + h U + b) */
+pub struct SimpleRNNCell {
+    pub activation: Box<dyn Activation>,
+}
+
+pub struct SimpleRNNCache {
+    v: Vec<f64>, // [x, h_prev]
+    z: Vec<f64>,
+}
+
+impl RecurrentCell for SimpleRNNCell {
+    type Cache = SimpleRNNCache;
+
+    fn name(&self) -> String {
+        format!("SimpleRNN {}", self.activation.name())
+    }
+
+    fn gates(&self) -> usize {
+        1
+    }
+
+    fn step(&self, kernel: &Array<f64>, bias: &Array<f64>, x: &[f64], h: &[f64], _c: &[f64]) -> (Vec<f64>, Vec<f64>, Self::Cache) {
+        let v = concat(x, h);
+        let z = affine(kernel, bias, &v, 0..h.len());
+        let h = z.iter().map(|&z| self.activation.activation(z)).collect();
+        (h, Vec::new(), SimpleRNNCache { v, z })
+    }
+
+    fn step_back(&self, kernel: &Array<f64>, cache: &Self::Cache, dh: &[f64], _dc: &[f64], dk: &mut Array<f64>, db: &mut Array<f64>)
+        -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let units = dh.len();
+        let dz: Vec<f64> = dh.iter().zip(cache.z.iter()).map(|(d, &z)| d * self.activation.derivative(z)).collect();
+        let mut dv = affine_back(kernel, &cache.v, &dz, 0..units, dk, db);
+        let dh_prev = dv.split_off(dv.len() - units);
+        (dv, dh_prev, Vec::new())
+    }
+}
+
+/* gates input i, forget f, candidate g, output o: c = f * c + i * g, h = o * tanh(c) */
+#[allow(clippy::upper_case_acronyms)]
+pub struct LSTMCell;
+
+pub struct LSTMCache {
+    v: Vec<f64>,
+    gates: Vec<f64>, // activated i, f, g, o
+    c_prev: Vec<f64>,
+    tanh_c: Vec<f64>,
+}
+
+impl RecurrentCell for LSTMCell {
+    type Cache = LSTMCache;
+
+    fn name(&self) -> String {
+        "LSTM".to_string()
+    }
+
+    fn gates(&self) -> usize {
+        4
+    }
+
+    fn has_cell_state(&self) -> bool {
+        true
+    }
+
+    /* forget gate bias of 1 to remember by default */
+    fn init_bias(&self, bias: &mut Array<f64>, units: usize) {
+        bias.data[units..2 * units].iter_mut().for_each(|b| *b = 1.0);
+    }
+
+    fn step(&self, kernel: &Array<f64>, bias: &Array<f64>, x: &[f64], h: &[f64], c: &[f64]) -> (Vec<f64>, Vec<f64>, Self::Cache) {
+        let units = h.len();
+        let v = concat(x, h);
+        let mut gates = affine(kernel, bias, &v, 0..4 * units);
+        for (j, a) in gates.iter_mut().enumerate() {
+            *a = if j / units == 2 { TanH.activation(*a) } else { Sigmoid.activation(*a) };
+        }
+        let (i, f, g, o) = (&gates[..units], &gates[units..2 * units], &gates[2 * units..3 * units], &gates[3 * units..]);
+        let c_new: Vec<f64> = (0..units).map(|j| f[j] * c[j] + i[j] * g[j]).collect();
+        let tanh_c: Vec<f64> = c_new.iter().map(|c| c.tanh()).collect();
+        let h_new = (0..units).map(|j| o[j] * tanh_c[j]).collect();
+        (h_new, c_new.clone(), LSTMCache { v, c_prev: c.to_vec(), tanh_c, gates })
+    }
+
+    fn step_back(&self, kernel: &Array<f64>, cache: &Self::Cache, dh: &[f64], dc: &[f64], dk: &mut Array<f64>, db: &mut Array<f64>)
+        -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let units = dh.len();
+        let gates = &cache.gates;
+        let (i, f, g, o) = (&gates[..units], &gates[units..2 * units], &gates[2 * units..3 * units], &gates[3 * units..]);
+        let mut dz = vec![0.0; 4 * units];
+        let mut dc_prev = vec![0.0; units];
+        for j in 0..units {
+            let tc = cache.tanh_c[j];
+            let dc = dc[j] + dh[j] * o[j] * (1.0 - tc * tc);
+            dz[j] = dc * g[j] * i[j] * (1.0 - i[j]);
+            dz[units + j] = dc * cache.c_prev[j] * f[j] * (1.0 - f[j]);
+            dz[2 * units + j] = dc * i[j] * (1.0 - g[j] * g[j]);
+            dz[3 * units + j] = dh[j] * tc * o[j] * (1.0 - o[j]);
+            dc_prev[j] = dc * f[j];
+        }
+        let mut dv = affine_back(kernel, &cache.v, &dz, 0..4 * units, dk, db);
+        let dh_prev = dv.split_off(dv.len() - units);
+        (dv, dh_prev, dc_prev)
+    }
+}
+
+/* gates update z, reset r and candidate n = tanh(x Wn + (r * h) Un + bn): h = z * h + (1 - z) * n */
+#[allow(clippy::upper_case_acronyms)]
+pub struct GRUCell;
+
+pub struct GRUCache {
+    v: Vec<f64>,  // [x, h_prev]
+    u: Vec<f64>,  // [x, r * h_prev]
+    zr: Vec<f64>, // activated z, r
+    n: Vec<f64>,
+}
+
+impl RecurrentCell for GRUCell {
+    type Cache = GRUCache;
+
+    fn name(&self) -> String {
+        "GRU".to_string()
+    }
+
+    fn gates(&self) -> usize {
+        3
+    }
+
+    fn step(&self, kernel: &Array<f64>, bias: &Array<f64>, x: &[f64], h: &[f64], _c: &[f64]) -> (Vec<f64>, Vec<f64>, Self::Cache) {
+        let units = h.len();
+        let v = concat(x, h);
+        let zr: Vec<f64> = affine(kernel, bias, &v, 0..2 * units).iter().map(|&a| Sigmoid.activation(a)).collect();
+        let rh: Vec<f64> = (0..units).map(|j| zr[units + j] * h[j]).collect();
+        let u = concat(x, &rh);
+        let n: Vec<f64> = affine(kernel, bias, &u, 2 * units..3 * units).iter().map(|a| a.tanh()).collect();
+        let h_new = (0..units).map(|j| zr[j] * h[j] + (1.0 - zr[j]) * n[j]).collect();
+        (h_new, Vec::new(), GRUCache { v, u, zr, n })
+    }
+
+    fn step_back(&self, kernel: &Array<f64>, cache: &Self::Cache, dh: &[f64], _dc: &[f64], dk: &mut Array<f64>, db: &mut Array<f64>)
+        -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+        let units = dh.len();
+        let features = cache.v.len() - units;
+        let h_prev = &cache.v[features..];
+        let (z, r) = (&cache.zr[..units], &cache.zr[units..]);
+
+        // candidate
+        let dn: Vec<f64> = (0..units).map(|j| dh[j] * (1.0 - z[j]) * (1.0 - cache.n[j] * cache.n[j])).collect();
+        let du = affine_back(kernel, &cache.u, &dn, 2 * units..3 * units, dk, db);
+        let mut dx = du[..features].to_vec();
+        let mut dh_prev: Vec<f64> = (0..units).map(|j| dh[j] * z[j] + du[features + j] * r[j]).collect();
+
+        // update and reset gates
+        let mut dzr = vec![0.0; 2 * units];
+        for j in 0..units {
+            dzr[j] = dh[j] * (h_prev[j] - cache.n[j]) * z[j] * (1.0 - z[j]);
+            dzr[units + j] = du[features + j] * h_prev[j] * r[j] * (1.0 - r[j]);
+        }
+        let dv = affine_back(kernel, &cache.v, &dzr, 0..2 * units, dk, db);
+        for (d, v) in dx.iter_mut().zip(dv[..features].iter()) {
+            *d += v;
+        }
+        for (d, v) in dh_prev.iter_mut().zip(dv[features..].iter()) {
+            *d += v;
+        }
+        (dx, dh_prev, Vec::new())
+    }
+}
+
+/* recurrent layer over [timesteps, features] inputs, the output is [timesteps, units] with return_sequences,
+ * the last state [1, units] otherwise,
+ * masked timesteps are skipped: the state is carried over them unchanged and is their output
+ */
+pub struct RecurrentLayer<C: RecurrentCell> {
+    pub cell: C,
+    pub units: usize,
+    pub return_sequences: bool,
+    pub truncation: Option<usize>,
+    pub mask: Vec<bool>,                // false for the padded timesteps, empty when unmasked
+    pub steps: Vec<Option<C::Cache>>,   // None for the skipped timesteps
+    pub weights: Array<f64>, // kernel, see RecurrentCell
+    pub bias: Array<f64>,
+    pub initializer: Initializer,           // rows of the input
+    pub recurrent_initializer: Initializer, // rows of the previous state
+    pub bias_initializer: Initializer,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+pub type SimpleRNNLayer = RecurrentLayer<SimpleRNNCell>;
+pub type LSTMLayer = RecurrentLayer<LSTMCell>;
+pub type GRULayer = RecurrentLayer<GRUCell>;
+
+impl SimpleRNNLayer {
+    /* tanh activation */
+    pub fn new(units: usize) -> Self {
+        RecurrentLayer::with_cell(SimpleRNNCell { activation: Box::new(TanH) }, units)
+    }
+
+    pub fn with_activation<A: Activation + 'static>(mut self, activation: A) -> Self {
+        self.cell.activation = Box::new(activation);
+        self
+    }
+}
+
+impl LSTMLayer {
+    pub fn new(units: usize) -> Self {
+        RecurrentLayer::with_cell(LSTMCell, units)
+    }
+}
+
+impl GRULayer {
+    pub fn new(units: usize) -> Self {
+        RecurrentLayer::with_cell(GRUCell, units)
+    }
+}
+
+impl<C: RecurrentCell> RecurrentLayer<C> {
+    pub fn with_cell(cell: C, units: usize) -> Self {
+        RecurrentLayer {
+            cell,
+            units,
+            return_sequences: false,
+            truncation: None,
+            mask: Vec::new(),
+            steps: Vec::new(),
+            weights: Array::<f64>::empty(),
+            bias: Array::<f64>::empty(),
+            initializer: Initializer::GlorotUniform,
+            recurrent_initializer: Initializer::Orthogonal(1.0),
+            bias_initializer: Initializer::Zeros,
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    /* output the state of every timestep instead of the last one */
+    pub fn with_return_sequences(mut self) -> Self {
+        self.return_sequences = true;
+        self
+    }
+
+    pub fn with_initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
+    }
+
+    pub fn with_recurrent_initializer(mut self, initializer: Initializer) -> Self {
+        self.recurrent_initializer = initializer;
+        self
+    }
+
+    pub fn with_bias_initializer(mut self, initializer: Initializer) -> Self {
+        self.bias_initializer = initializer;
+        self
+    }
+}
+
+impl<C: RecurrentCell> Layer for RecurrentLayer<C> {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Recurrent", &self.input_shape, &input.shape);
+
+        let (timesteps, features) = (input.shape[0], input.shape[1]);
+        let units = self.units;
+        let mut h = vec![0.0; units];
+        let mut c = if self.cell.has_cell_state() { vec![0.0; units] } else { Vec::new() };
+        let mut sequence: Vec<f64> = Vec::with_capacity(timesteps * units);
+        self.steps = Vec::with_capacity(timesteps);
+        for t in 0..timesteps {
+            if self.mask.get(t) == Some(&false) {
+                self.steps.push(None);
+            } else {
+                let x = &input.data[t * features..(t + 1) * features];
+                let cache;
+                (h, c, cache) = self.cell.step(&self.weights, &self.bias, x, &h, &c);
+                self.steps.push(Some(cache));
+            }
+            if self.return_sequences {
+                sequence.extend_from_slice(&h);
+            }
+        }
+        if self.return_sequences {
+            Array::<f64>::with(&[timesteps, units], &sequence)
+        } else {
+            Array::<f64>::with(&[1, units], &h)
+        }
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let timesteps = self.steps.len();
+        let features = self.input_shape[1];
+        let units = self.units;
+        let mut input_error = Array::<f64>::zeros(&[timesteps, features]);
+        let mut weights_error = Array::<f64>::zeros(&self.weights.shape);
+        let mut bias_error = Array::<f64>::zeros(&self.bias.shape);
+
+        let mut dh = vec![0.0; units];
+        let mut dc = if self.cell.has_cell_state() { vec![0.0; units] } else { Vec::new() };
+        for t in (0..timesteps).rev() {
+            let row = if self.return_sequences { Some(t) } else if t == timesteps - 1 { Some(0) } else { None };
+            if let Some(row) = row {
+                for (d, e) in dh.iter_mut().zip(error.data[row * units..(row + 1) * units].iter()) {
+                    *d += e;
+                }
+            }
+            // a skipped timestep passes the state gradient on to the previous one
+            if let Some(cache) = &self.steps[t] {
+                let (dx, dh_prev, dc_prev) = self.cell.step_back(&self.weights, cache, &dh, &dc, &mut weights_error, &mut bias_error);
+                input_error.data[t * features..(t + 1) * features].copy_from_slice(&dx);
+                (dh, dc) = (dh_prev, dc_prev);
+            }
+            // truncated bptt: no gradient flows into the previous chunk of timesteps
+            if self.truncation.is_some_and(|k| (timesteps - t) % k == 0) {
+                dh.iter_mut().chain(dc.iter_mut()).for_each(|d| *d = 0.0);
+            }
+        }
+        self.steps.clear();
+        (input_error, Some(weights_error), Some(bias_error))
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        self.weights.add_m(delta_weights);
+        self.bias.add_m(delta_bias);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        if self.weights.shape != weights.shape || self.bias.shape != bias.shape {
+            panic!("[{}] weights shape not match.", self.cell.name());
+        }
+        self.weights = weights;
+        self.bias = bias;
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        Some((self.weights.clone(), self.bias.clone()))
+    }
+
+    fn set_truncation(&mut self, steps: Option<usize>) {
+        self.truncation = steps;
+    }
+
+    fn set_mask(&mut self, mask: Option<&[bool]>) {
+        self.mask = mask.map_or_else(Vec::new, |m| m.to_vec());
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 2 {
+            panic!("[{}] input must be [timesteps, features].", self.cell.name());
+        }
+        let (timesteps, features) = (prev_output_shape[0], prev_output_shape[1]);
+        let width = self.cell.gates() * self.units;
+        self.weights = Array::<f64>::zeros(&[features + self.units, width]);
+        self.bias = Array::<f64>::zeros(&[1, width]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([if self.return_sequences { timesteps } else { 1 }, self.units]);
+        println!("[{}] config input shape: {:?}, output shape: {:?}", self.cell.name(), self.input_shape, self.output_shape);
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        let (features, width) = (self.input_shape[1], self.weights.shape[1]);
+        let kernel = self.initializer.initialize(&[features, width], features, width, rng);
+        let recurrent = self.recurrent_initializer.initialize(&[self.units, width], self.units, width, rng);
+        self.weights = Array::<f64>::with(&self.weights.shape, &[kernel.data, recurrent.data].concat());
+        self.bias = self.bias_initializer.initialize(&self.bias.shape, features, width, rng);
+        self.cell.init_bias(&mut self.bias, self.units);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* runs `forward` on the sequence and `backward` on the reversed sequence, the outputs are concatenated on the last axis,
+ * with return_sequences the output of `backward` is reversed back to align the timesteps,
+ * the parameters of both are packed into one [1, n] weights and one [1, n] bias array
+ */
+pub struct BidirectionalLayer {
+    pub forward: Box<dyn Layer>,
+    pub backward: Box<dyn Layer>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl BidirectionalLayer {
+    pub fn new<F: Layer + 'static, B: Layer + 'static>(forward: F, backward: B) -> Self {
+        BidirectionalLayer {
+            forward: Box::new(forward),
+            backward: Box::new(backward),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    fn units(&self) -> (usize, usize) {
+        (self.forward.get_output_shape()[1], self.backward.get_output_shape()[1])
+    }
+}
+
+/* the rows (timesteps) in reverse order */
+fn reverse_rows(array: &Array<f64>) -> Array<f64> {
+    let cols = array.data.len() / array.shape[0];
+    let data: Vec<f64> = array.data.chunks(cols).rev().flatten().copied().collect();
+    Array::<f64>::with(&array.shape, &data)
+}
+
+impl Layer for BidirectionalLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Bidirectional", &self.input_shape, &input.shape);
+
+        let reversed = reverse_rows(&input);
+        let out_f = self.forward.forward_prop(input);
+        let out_b = reverse_rows(&self.backward.forward_prop(reversed));
+        let (uf, ub) = self.units();
+        let rows = out_f.shape[0];
+        let mut data = Vec::with_capacity(rows * (uf + ub));
+        for r in 0..rows {
+            data.extend_from_slice(&out_f.data[r * uf..(r + 1) * uf]);
+            data.extend_from_slice(&out_b.data[r * ub..(r + 1) * ub]);
+        }
+        Array::<f64>::with(&[rows, uf + ub], &data)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let (uf, ub) = self.units();
+        let rows = error.shape[0];
+        let mut ef = Vec::with_capacity(rows * uf);
+        let mut eb = Vec::with_capacity(rows * ub);
+        for r in 0..rows {
+            let row = &error.data[r * (uf + ub)..(r + 1) * (uf + ub)];
+            ef.extend_from_slice(&row[..uf]);
+            eb.extend_from_slice(&row[uf..]);
+        }
+        let (mut input_error, wf, bf) = self.forward.backward_prop(Array::<f64>::with(&[rows, uf], &ef));
+        let (input_error_b, wb, bb) = self.backward.backward_prop(reverse_rows(&Array::<f64>::with(&[rows, ub], &eb)));
+        input_error.add_m(&reverse_rows(&input_error_b));
+
+        let (weights, bias) = pack_sublayer_deltas(vec![wf, wb], vec![bf, bb]);
+        (input_error, weights, bias)
+    }
+
+    fn update_parameters(&mut self, delta_weights: &Array<f64>, delta_bias: &Array<f64>) {
+        update_sublayer_parameters(&mut [&mut *self.forward, &mut *self.backward], delta_weights, delta_bias);
+    }
+
+    fn set_parameters(&mut self, weights: Array<f64>, bias: Array<f64>) {
+        set_sublayer_parameters(&mut [&mut *self.forward, &mut *self.backward], weights, bias);
+    }
+
+    fn get_parameters(&self) -> Option<(Array<f64>, Array<f64>)> {
+        pack_sublayer_parameters(&[&*self.forward, &*self.backward])
+    }
+
+    fn init_parameters(&mut self, rng: &mut StdRng) {
+        self.forward.init_parameters(rng);
+        self.backward.init_parameters(rng);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.forward.set_training(training);
+        self.backward.set_training(training);
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.forward.reseed(seed);
+        self.backward.reseed(seed.wrapping_add(1));
+    }
+
+    fn set_truncation(&mut self, steps: Option<usize>) {
+        self.forward.set_truncation(steps);
+        self.backward.set_truncation(steps);
+    }
+
+    fn set_mask(&mut self, mask: Option<&[bool]>) {
+        self.forward.set_mask(mask);
+        let reversed: Option<Vec<bool>> = mask.map(|m| m.iter().rev().copied().collect());
+        self.backward.set_mask(reversed.as_deref());
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        self.forward.config_shape(prev_output_shape);
+        self.backward.config_shape(prev_output_shape);
+        let (uf, ub) = self.units();
+        if self.forward.get_output_shape()[0] != self.backward.get_output_shape()[0] {
+            panic!("[Bidirectional] forward and backward layers return different timesteps.");
+        }
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([self.forward.get_output_shape()[0], uf + ub]);
+        println!("[Bidirectional] config input shape: {:?}, output shape: {:?}", self.input_shape, self.output_shape);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    #[test]
+    fn simple_rnn_gradients() {
+        check_layer(&mut SimpleRNNLayer::new(3), &[4, 2], true);
+        check_layer(&mut SimpleRNNLayer::new(3).with_return_sequences(), &[4, 2], true);
+    }
+
+    #[test]
+    fn lstm_gradients() {
+        check_layer(&mut LSTMLayer::new(3).with_return_sequences(), &[4, 2], true);
+    }
+
+    #[test]
+    fn gru_gradients() {
+        check_layer(&mut GRULayer::new(3).with_return_sequences(), &[4, 2], true);
+    }
+
+    #[test]
+    fn bidirectional_gradients() {
+        check_layer(&mut BidirectionalLayer::new(GRULayer::new(2), LSTMLayer::new(3)), &[4, 2], true);
+    }
+}
@@ -0,0 +1,143 @@
+use super::shape::Array;
+
+/* weight penalty added to the loss, its gradient is folded into the layer's delta weights */
+#[derive(Clone, Debug)]
+pub enum Regularizer {
+    L1(f64),            // l1 * sum(|w|)
+    L2(f64),            // l2 * sum(w^2)
+    ElasticNet(f64, f64), // (l1, l2), both penalties
+}
+
+impl Regularizer {
+    fn factors(&self) -> (f64, f64) {
+        match *self {
+            Regularizer::L1(l1) => (l1, 0.0),
+            Regularizer::L2(l2) => (0.0, l2),
+            Regularizer::ElasticNet(l1, l2) => (l1, l2),
+        }
+    }
+
+    pub fn penalty(&self, weights: &Array<f64>) -> f64 {
+        let (l1, l2) = self.factors();
+        weights.data.iter().map(|w| l1 * w.abs() + l2 * w * w).sum()
+    }
+
+    pub fn gradient(&self, weights: &Array<f64>) -> Array<f64> {
+        let (l1, l2) = self.factors();
+        let mut grad = weights.clone();
+        for g in grad.data.iter_mut() {
+            let sign = if *g > 0.0 { 1.0 } else if *g < 0.0 { -1.0 } else { 0.0 };
+            *g = l1 * sign + 2.0 * l2 * *g;
+        }
+        grad
+    }
+}
+
+/* projection applied to the parameters after every update */
+#[derive(Clone, Debug)]
+pub enum Constraint {
+    MaxNorm(f64),       // L2 norm of the weights of each unit (last dimension) is at most the value
+    NonNeg,             // negative weights are set to zero
+}
+
+impl Constraint {
+    pub fn apply(&self, weights: &mut Array<f64>) {
+        match *self {
+            Constraint::MaxNorm(max) => {
+                let units = weights.shape[weights.shape.len() - 1];
+                let mut norms = vec![0.0; units];
+                for (i, w) in weights.data.iter().enumerate() {
+                    norms[i % units] += w * w;
+                }
+                for (i, w) in weights.data.iter_mut().enumerate() {
+                    let norm = norms[i % units].sqrt();
+                    if norm > max {
+                        *w *= max / norm;
+                    }
+                }
+            }
+            Constraint::NonNeg => weights.data.iter_mut().for_each(|w| *w = w.max(0.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::layer::{Layer, DenseLayer};
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert!(actual.len() == expected.len() && actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() < 1e-12),
+            "{:?} expected {:?}", actual, expected);
+    }
+
+    fn weights() -> Array<f64> {
+        Array::<f64>::with(&[2, 2], &[1.0, -2.0, 0.0, 0.5])
+    }
+
+    #[test]
+    fn penalties() {
+        // sum(|w|) = 3.5, sum(w^2) = 5.25
+        assert_close(&[Regularizer::L1(0.1).penalty(&weights())], &[0.35]);
+        assert_close(&[Regularizer::L2(0.1).penalty(&weights())], &[0.525]);
+        assert_close(&[Regularizer::ElasticNet(0.1, 0.2).penalty(&weights())], &[0.35 + 1.05]);
+    }
+
+    #[test]
+    fn gradients() {
+        // the sign of a zero weight is 0
+        assert_close(&Regularizer::L1(0.1).gradient(&weights()).data, &[0.1, -0.1, 0.0, 0.1]);
+        assert_close(&Regularizer::L2(0.1).gradient(&weights()).data, &[0.2, -0.4, 0.0, 0.1]);
+        assert_close(&Regularizer::ElasticNet(0.1, 0.1).gradient(&weights()).data, &[0.3, -0.5, 0.0, 0.2]);
+    }
+
+    #[test]
+    fn constraints() {
+        // the columns (units) have norms sqrt(1) and sqrt(4.25)
+        let mut w = weights();
+        Constraint::MaxNorm(1.5).apply(&mut w);
+        let scale = 1.5 / 4.25f64.sqrt();
+        assert_close(&w.data, &[1.0, -2.0 * scale, 0.0, 0.5 * scale]);
+        let mut w = weights();
+        Constraint::NonNeg.apply(&mut w);
+        assert_eq!(w.data.to_vec(), vec![1.0, 0.0, 0.0, 0.5]);
+    }
+
+    /* a [1, 2] --> 2 DenseLayer with weights() and bias [0.5, -0.5] */
+    fn dense(layer: DenseLayer) -> DenseLayer {
+        let mut layer = layer;
+        layer.config_shape(&[1, 2]);
+        layer.set_parameters(weights(), Array::<f64>::with(&[1, 2], &[0.5, -0.5]));
+        layer
+    }
+
+    #[test]
+    fn dense_adds_the_penalty_gradient() {
+        let input = Array::<f64>::with(&[1, 2], &[0.3, -0.7]);
+        let error = Array::<f64>::with(&[1, 2], &[1.0, 2.0]);
+        let mut plain = dense(DenseLayer::new(2));
+        plain.forward_prop(input.clone());
+        let (_, w, b) = plain.backward_prop(error.clone());
+
+        let mut regularized = dense(DenseLayer::new(2).with_regularizer(Regularizer::L2(0.1)).with_bias_regularizer(Regularizer::L1(0.01)));
+        regularized.forward_prop(input);
+        let (_, rw, rb) = regularized.backward_prop(error);
+        let dw: Vec<f64> = rw.unwrap().data.iter().zip(w.unwrap().data.iter()).map(|(r, p)| r - p).collect();
+        let db: Vec<f64> = rb.unwrap().data.iter().zip(b.unwrap().data.iter()).map(|(r, p)| r - p).collect();
+        assert_close(&dw, &[0.2, -0.4, 0.0, 0.1]);
+        assert_close(&db, &[0.01, -0.01]);
+        assert_close(&[regularized.regularization_loss()], &[0.525 + 0.01]);
+    }
+
+    #[test]
+    fn dense_constrains_after_update() {
+        let mut layer = dense(DenseLayer::new(2).with_constraint(Constraint::MaxNorm(1.0)).with_bias_constraint(Constraint::NonNeg));
+        layer.update_parameters(&Array::<f64>::with(&[2, 2], &[2.0, 0.0, -3.0, 1.0]), &Array::<f64>::with(&[1, 2], &[-1.0, 0.2]));
+        let (w, b) = layer.get_parameters().unwrap();
+        for unit in 0..2 {
+            let norm = (w.data[unit].powi(2) + w.data[2 + unit].powi(2)).sqrt();
+            assert!(norm <= 1.0 + 1e-12, "unit {} norm {}", unit, norm);
+        }
+        assert_eq!(b.data.to_vec(), vec![0.0, 0.0]);
+    }
+}
@@ -0,0 +1,342 @@
+use super::{layer::{Layer, check_input_shape, check_batch_input_shape}, shape::Array};
+
+/* [d1, d2, ...] --> [1, d1 * d2 * ...] in row-major order, e.g. between Conv2DLayer and DenseLayer */
+pub struct FlattenLayer {
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl FlattenLayer {
+    pub fn new() -> Self {
+        FlattenLayer {
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+}
+
+impl Default for FlattenLayer {
+    fn default() -> Self {
+        FlattenLayer::new()
+    }
+}
+
+impl Layer for FlattenLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_batch_input_shape("Flatten", &self.input_shape, &input.shape);
+
+        // a stacked batch of [1, n] keeps one row per sample
+        let rows = input.data.len() / self.output_shape[1];
+        input.reshape(&[rows, self.output_shape[1]])
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        if self.batchable() {
+            return (error, None, None);
+        }
+        (error.reshape(&self.input_shape), None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([1, prev_output_shape.iter().product()]);
+        println!("[Flatten] config input shape: {:?}, output shape: {:?}", self.input_shape, self.output_shape);
+    }
+
+    fn batchable(&self) -> bool {
+        self.input_shape.len() == 2 && self.input_shape[0] == 1
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* same data in a target shape of the same size */
+pub struct ReshapeLayer {
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl ReshapeLayer {
+    pub fn new(target_shape: &[usize]) -> Self {
+        ReshapeLayer {
+            input_shape: Box::default(),
+            output_shape: target_shape.into(),
+        }
+    }
+}
+
+impl Layer for ReshapeLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Reshape", &self.input_shape, &input.shape);
+
+        input.reshape(&self.output_shape)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        (error.reshape(&self.input_shape), None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.iter().product::<usize>() != self.output_shape.iter().product() {
+            panic!("[Reshape] can not reshape {:?} into {:?}.", prev_output_shape, self.output_shape);
+        }
+        self.input_shape = prev_output_shape.into();
+        println!("[Reshape] config input shape: {:?}, output shape: {:?}", self.input_shape, self.output_shape);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* reorder the dimensions, dimension i of the output is dimension axes[i] of the input,
+ * e.g. [2, 0, 1] turns [rows, cols, ch] into [ch, rows, cols]
+ */
+pub struct PermuteLayer {
+    pub axes: Vec<usize>,
+    pub inverse: Vec<usize>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl PermuteLayer {
+    pub fn new(axes: &[usize]) -> Self {
+        PermuteLayer {
+            axes: axes.to_vec(),
+            inverse: Vec::new(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+}
+
+impl Layer for PermuteLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("Permute", &self.input_shape, &input.shape);
+
+        input.permute(&self.axes)
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        (error.permute(&self.inverse), None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        let dims = prev_output_shape.len();
+        if self.axes.len() != dims || (0..dims).any(|d| !self.axes.contains(&d)) {
+            panic!("[Permute] {:?} is not a permutation of {} dimensions.", self.axes, dims);
+        }
+        self.inverse = vec![0; dims];
+        for (i, &a) in self.axes.iter().enumerate() {
+            self.inverse[a] = i;
+        }
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = self.axes.iter().map(|&a| prev_output_shape[a]).collect();
+        println!("[Permute] config input shape: {:?}, output shape: {:?}", self.input_shape, self.output_shape);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+/* resampling of UpSampling2DLayer */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Nearest,    // every cell repeated size x size times
+    Bilinear,   // from the 4 nearest cells with half-pixel centers, clamped at the border
+}
+
+/* [rows, cols, ch] --> [rows * size, cols * size, ch] */
+pub struct UpSampling2DLayer {
+    pub size: usize,
+    pub interpolation: Interpolation,
+    pub row_taps: Vec<(usize, usize, f64)>, // (source row, next source row, weight of the next) of each output row
+    pub col_taps: Vec<(usize, usize, f64)>,
+    pub input_shape: Box<[usize]>,
+    pub output_shape: Box<[usize]>,
+}
+
+impl UpSampling2DLayer {
+    pub fn new(size: usize) -> Self {
+        UpSampling2DLayer {
+            size,
+            interpolation: Interpolation::Nearest,
+            row_taps: Vec::new(),
+            col_taps: Vec::new(),
+            input_shape: Box::default(),
+            output_shape: Box::default(),
+        }
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /* source cells of every output cell along an axis of `length` cells */
+    fn taps(&self, length: usize) -> Vec<(usize, usize, f64)> {
+        (0..length * self.size).map(|i| match self.interpolation {
+            Interpolation::Nearest => (i / self.size, i / self.size, 0.0),
+            Interpolation::Bilinear => {
+                let src = ((i as f64 + 0.5) / self.size as f64 - 0.5).max(0.0);
+                let low = (src as usize).min(length - 1);
+                (low, (low + 1).min(length - 1), src - low as f64)
+            }
+        }).collect()
+    }
+}
+
+impl Layer for UpSampling2DLayer {
+    fn forward_prop(&mut self, input: Array<f64>) -> Array<f64> {
+        check_input_shape("UpSampling2D", &self.input_shape, &input.shape);
+
+        let (cols, ch) = (self.input_shape[1], self.input_shape[2]);
+        let mut output = Array::<f64>::zeros(&self.output_shape);
+        let mut out = output.data.chunks_mut(ch);
+        for &(r0, r1, fr) in self.row_taps.iter() {
+            for &(c0, c1, fc) in self.col_taps.iter() {
+                let cell = out.next().unwrap();
+                for (r, wr) in [(r0, 1.0 - fr), (r1, fr)] {
+                    for (c, wc) in [(c0, 1.0 - fc), (c1, fc)] {
+                        let src = &input.data[(r * cols + c) * ch..(r * cols + c + 1) * ch];
+                        cell.iter_mut().zip(src.iter()).for_each(|(o, x)| *o += wr * wc * x);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn backward_prop(&mut self, error: Array<f64>) -> (Array<f64>, Option<Array<f64>>, Option<Array<f64>>) {
+        let (cols, ch) = (self.input_shape[1], self.input_shape[2]);
+        let mut input_error = Array::<f64>::zeros(&self.input_shape);
+        let mut err = error.data.chunks(ch);
+        for &(r0, r1, fr) in self.row_taps.iter() {
+            for &(c0, c1, fc) in self.col_taps.iter() {
+                let cell = err.next().unwrap();
+                for (r, wr) in [(r0, 1.0 - fr), (r1, fr)] {
+                    for (c, wc) in [(c0, 1.0 - fc), (c1, fc)] {
+                        let dst = &mut input_error.data[(r * cols + c) * ch..(r * cols + c + 1) * ch];
+                        dst.iter_mut().zip(cell.iter()).for_each(|(d, e)| *d += wr * wc * e);
+                    }
+                }
+            }
+        }
+        (input_error, None, None)
+    }
+
+    fn config_shape(&mut self, prev_output_shape: &[usize]) {
+        if prev_output_shape.len() != 3 {
+            panic!("[UpSampling2D] input must be [rows, cols, ch].");
+        }
+        self.row_taps = self.taps(prev_output_shape[0]);
+        self.col_taps = self.taps(prev_output_shape[1]);
+        self.input_shape = prev_output_shape.into();
+        self.output_shape = Box::new([prev_output_shape[0] * self.size, prev_output_shape[1] * self.size, prev_output_shape[2]]);
+        println!("[UpSampling2D] config input shape: {:?}, output shape: {:?}, {:?}", self.input_shape, self.output_shape, self.interpolation);
+    }
+
+    fn get_output_shape(&self) -> &[usize] {
+        &self.output_shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::gradient_check::check_layer;
+
+    /* config `layer` for `input_shape`, check the output shape and that backward_prop undoes forward_prop */
+    fn round_trip(layer: &mut dyn Layer, input_shape: &[usize], output_shape: &[usize]) -> Array<f64> {
+        layer.config_shape(input_shape);
+        assert_eq!(layer.get_output_shape(), output_shape);
+        let len = input_shape.iter().product::<usize>();
+        let input = Array::<f64>::with(input_shape, &(0..len).map(|i| i as f64).collect::<Vec<f64>>());
+        let output = layer.forward_prop(input.clone());
+        assert_eq!(&*output.shape, output_shape);
+        let (back, _, _) = layer.backward_prop(output.clone());
+        assert_eq!(back.shape, input.shape);
+        assert_eq!(back.data, input.data);
+        output
+    }
+
+    /* value round_trip() puts at `index` of an input of `shape` */
+    fn input_index(shape: &[usize], index: &[usize]) -> f64 {
+        index.iter().zip(shape.iter()).fold(0, |flat, (i, d)| flat * d + i) as f64
+    }
+
+    #[test]
+    fn flatten() {
+        let output = round_trip(&mut FlattenLayer::new(), &[2, 3, 4], &[1, 24]);
+        assert_eq!(output.data[5], 5.0);
+        check_layer(&mut FlattenLayer::new(), &[2, 3, 4], true);
+
+        // a stacked batch keeps one row per sample
+        let mut flatten = FlattenLayer::new();
+        flatten.config_shape(&[1, 3]);
+        let stacked = flatten.forward_prop(Array::<f64>::zeros(&[4, 3]));
+        assert_eq!(&*stacked.shape, &[4, 3]);
+    }
+
+    #[test]
+    fn reshape() {
+        round_trip(&mut ReshapeLayer::new(&[4, 6]), &[2, 3, 4], &[4, 6]);
+        round_trip(&mut ReshapeLayer::new(&[2, 2, 2]), &[1, 8], &[2, 2, 2]);
+        check_layer(&mut ReshapeLayer::new(&[3, 8]), &[2, 3, 4], true);
+    }
+
+    #[test]
+    #[should_panic(expected = "[Reshape] can not reshape [2, 3] into [4, 2].")]
+    fn reshape_to_another_size_panics() {
+        ReshapeLayer::new(&[4, 2]).config_shape(&[2, 3]);
+    }
+
+    #[test]
+    fn permute() {
+        // [rows, cols, ch] --> [ch, rows, cols]: the value at (r, c, k) moves to (k, r, c)
+        let output = round_trip(&mut PermuteLayer::new(&[2, 0, 1]), &[2, 3, 4], &[4, 2, 3]);
+        assert_eq!(output[&[3, 1, 2]], input_index(&[2, 3, 4], &[1, 2, 3]));
+        round_trip(&mut PermuteLayer::new(&[1, 0]), &[2, 5], &[5, 2]);
+        check_layer(&mut PermuteLayer::new(&[1, 2, 0]), &[2, 3, 4], true);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a permutation")]
+    fn permute_with_a_repeated_axis_panics() {
+        PermuteLayer::new(&[0, 0, 1]).config_shape(&[2, 3, 4]);
+    }
+
+    #[test]
+    fn up_sampling_nearest() {
+        let mut layer = UpSampling2DLayer::new(2);
+        layer.config_shape(&[2, 2, 1]);
+        assert_eq!(layer.get_output_shape(), &[4, 4, 1]);
+        let output = layer.forward_prop(Array::<f64>::with(&[2, 2, 1], &[1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(output.data.to_vec(), vec![1.0, 1.0, 2.0, 2.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 3.0, 3.0, 4.0, 4.0]);
+        // every input cell sums the error of its size x size copies
+        let (back, _, _) = layer.backward_prop(Array::<f64>::fill(&[4, 4, 1], 1.0));
+        assert_eq!(back.data.to_vec(), vec![4.0; 4]);
+
+        check_layer(&mut UpSampling2DLayer::new(2), &[2, 3, 2], true);
+        check_layer(&mut UpSampling2DLayer::new(3), &[2, 2, 1], true);
+    }
+
+    #[test]
+    fn up_sampling_bilinear() {
+        let mut layer = UpSampling2DLayer::new(2).with_interpolation(Interpolation::Bilinear);
+        layer.config_shape(&[2, 2, 1]);
+        let output = layer.forward_prop(Array::<f64>::with(&[2, 2, 1], &[1.0, 2.0, 3.0, 4.0]));
+        // half-pixel centers, the border rows and columns are clamped
+        assert_eq!(output.data[..8].to_vec(), vec![1.0, 1.25, 1.75, 2.0, 1.5, 1.75, 2.25, 2.5]);
+        assert_eq!(output.data[12..].to_vec(), vec![3.0, 3.25, 3.75, 4.0]);
+        // the interpolation weights of every output cell sum to 1
+        let (back, _, _) = layer.backward_prop(Array::<f64>::fill(&[4, 4, 1], 1.0));
+        assert_eq!(back.data.iter().sum::<f64>(), 16.0);
+
+        check_layer(&mut UpSampling2DLayer::new(2).with_interpolation(Interpolation::Bilinear), &[2, 3, 2], true);
+        check_layer(&mut UpSampling2DLayer::new(3).with_interpolation(Interpolation::Bilinear), &[3, 2, 1], true);
+    }
+}
@@ -1,6 +1,6 @@
 use core::panic;
 use std::{ops::{Index, IndexMut}};
-use rand::Rng;
+use rand::{Rng, seq::SliceRandom};
 #[derive(Clone, Debug)]
 pub struct Array<T> {
     pub shape: Box<[usize]>,
@@ -10,20 +10,97 @@ pub struct Array<T> {
 
 macro_rules! new_float_impl_for_array {
     ($type:ident) => {
-        #[allow(dead_code)]
         impl Array<$type> {
             pub fn random_default(shape_: &[usize]) -> Self {
                 Self::random(shape_, -1.0, 1.0)
             }
 
             pub fn random(shape_: &[usize], low: $type, high: $type) -> Self {
-                let mut rng = rand::thread_rng();
+                Self::random_with(shape_, low, high, &mut rand::thread_rng())
+            }
+
+            /* uniform in [low, high) drawn from the given generator */
+            pub fn random_with<R: Rng + ?Sized>(shape_: &[usize], low: $type, high: $type, rng: &mut R) -> Self {
                 let (shape, sub_size) = Self::parse_shape(shape_);
         
                 let data = (0..sub_size[0]).map(|_| rng.gen_range(low..high)).collect();
                 Array { shape, sub_size, data }
             }
 
+            /* standard normal sample, Box-Muller transform */
+            fn sample_normal<R: Rng + ?Sized>(rng: &mut R) -> $type {
+                let u1: $type = 1.0 - rng.gen::<$type>(); // (0, 1], keeps ln finite
+                let u2: $type = rng.gen();
+                (-2.0 * u1.ln()).sqrt() * (2.0 * std::$type::consts::PI * u2).cos()
+            }
+
+            pub fn normal(shape_: &[usize], mean: $type, std: $type) -> Self {
+                Self::normal_with(shape_, mean, std, &mut rand::thread_rng())
+            }
+
+            pub fn normal_with<R: Rng + ?Sized>(shape_: &[usize], mean: $type, std: $type, rng: &mut R) -> Self {
+                let (shape, sub_size) = Self::parse_shape(shape_);
+
+                let data = (0..sub_size[0]).map(|_| mean + std * Self::sample_normal(rng)).collect();
+                Array { shape, sub_size, data }
+            }
+
+            /* normal samples further than 2 std from the mean are redrawn */
+            pub fn truncated_normal(shape_: &[usize], mean: $type, std: $type) -> Self {
+                Self::truncated_normal_with(shape_, mean, std, &mut rand::thread_rng())
+            }
+
+            pub fn truncated_normal_with<R: Rng + ?Sized>(shape_: &[usize], mean: $type, std: $type, rng: &mut R) -> Self {
+                let (shape, sub_size) = Self::parse_shape(shape_);
+
+                let data = (0..sub_size[0]).map(|_| {
+                    let mut z = Self::sample_normal(rng);
+                    while z.abs() > 2.0 {
+                        z = Self::sample_normal(rng);
+                    }
+                    mean + std * z
+                }).collect();
+                Array { shape, sub_size, data }
+            }
+
+            /* 1 with probability p, 0 otherwise */
+            pub fn bernoulli(shape_: &[usize], p: $type) -> Self {
+                Self::bernoulli_with(shape_, p, &mut rand::thread_rng())
+            }
+
+            pub fn bernoulli_with<R: Rng + ?Sized>(shape_: &[usize], p: $type, rng: &mut R) -> Self {
+                let (shape, sub_size) = Self::parse_shape(shape_);
+
+                let data = (0..sub_size[0]).map(|_| if rng.gen::<$type>() < p { 1.0 } else { 0.0 }).collect();
+                Array { shape, sub_size, data }
+            }
+
+            /* class indices drawn from the (unnormalized) weights `probs` */
+            pub fn categorical(shape_: &[usize], probs: &[$type]) -> Self {
+                Self::categorical_with(shape_, probs, &mut rand::thread_rng())
+            }
+
+            pub fn categorical_with<R: Rng + ?Sized>(shape_: &[usize], probs: &[$type], rng: &mut R) -> Self {
+                let total: $type = probs.iter().sum();
+                if probs.is_empty() || total <= 0.0 || probs.iter().any(|&p| p < 0.0) {
+                    panic!("Categorical with invalid probabilities");
+                }
+                let (shape, sub_size) = Self::parse_shape(shape_);
+
+                let data = (0..sub_size[0]).map(|_| {
+                    let mut u = rng.gen::<$type>() * total;
+                    for (i, &p) in probs.iter().enumerate() {
+                        if u < p {
+                            return i as $type;
+                        }
+                        u -= p;
+                    }
+                    // rounding left u at the very end
+                    (probs.len() - 1) as $type
+                }).collect();
+                Array { shape, sub_size, data }
+            }
+
             pub fn get_zero() -> $type { 0.0 }
         }
     }
@@ -31,14 +108,17 @@ macro_rules! new_float_impl_for_array {
 
 macro_rules! new_int_impl_for_array {
     ($type:ident) => {
-        #[allow(dead_code)]
         impl Array<$type> {
             pub fn random_default(shape_: &[usize]) -> Self {
                 Self::random(shape_, -128, 127)
             }
 
             pub fn random(shape_: &[usize], low: $type, high: $type) -> Self {
-                let mut rng = rand::thread_rng();
+                Self::random_with(shape_, low, high, &mut rand::thread_rng())
+            }
+
+            /* uniform in [low, high) drawn from the given generator */
+            pub fn random_with<R: Rng + ?Sized>(shape_: &[usize], low: $type, high: $type, rng: &mut R) -> Self {
                 let (shape, sub_size) = Self::parse_shape(shape_);
         
                 let data = (0..sub_size[0]).map(|_| rng.gen_range(low..high)).collect();
@@ -52,7 +132,6 @@ macro_rules! new_int_impl_for_array {
 
 macro_rules! new_impl_for_array {
     ($type:ident) => {
-        #[allow(dead_code)]
         impl Array<$type> {
             pub fn empty() -> Self {
                 Array { shape: Box::default(), sub_size: Box::default(), data: Box::default() }
@@ -95,7 +174,7 @@ macro_rules! new_impl_for_array {
                 Array { shape, sub_size, data: vec![value; size].into_boxed_slice() }
             }
 
-            pub fn to_vec(self) -> Vec<$type> {
+            pub fn into_vec(self) -> Vec<$type> {
                 self.data.into_vec()
             }
 
@@ -152,7 +231,7 @@ macro_rules! new_impl_for_array {
                     
                     for i in 0..rows {
                         for j in 0..cols {
-                            temp.data[j * rows + i] = self.data[i * cols + j];
+                            temp.data[i * cols + j] = self.data[j * rows + i];
                         }
                     }
                     temp
@@ -162,7 +241,7 @@ macro_rules! new_impl_for_array {
             /* add a constant value */
             pub fn add_v(&mut self, rhs: $type) -> &Self {
                 for i in 0..self.data.len() {
-                    self.data[i] = self.data[i] + rhs;
+                    self.data[i] += rhs;
                 }
                 self
             }
@@ -173,7 +252,7 @@ macro_rules! new_impl_for_array {
                     println!("add_m: dim not match");
                 } else {
                     for i in 0..self.data.len() {
-                        self.data[i] = self.data[i] + rhs.data[i];
+                        self.data[i] += rhs.data[i];
                     }
                 }
                 self
@@ -182,7 +261,62 @@ macro_rules! new_impl_for_array {
             /* multiplied by a constant */
             pub fn mul_v(&mut self, rhs: $type) -> &Self {
                 for i in 0..self.data.len() {
-                    self.data[i] = self.data[i] * rhs;
+                    self.data[i] *= rhs;
+                }
+                self
+            }
+
+            /* same data in a new shape of the same size */
+            pub fn reshape(mut self, shape_: &[usize]) -> Self {
+                let (shape, sub_size) = Self::parse_shape(shape_);
+                if sub_size[0] != self.data.len() {
+                    panic!("reshape: size not match");
+                }
+                self.shape = shape;
+                self.sub_size = sub_size;
+                self
+            }
+
+            /* reorder the dimensions, dimension i of the result is dimension axes[i] of self */
+            pub fn permute(&self, axes: &[usize]) -> Self {
+                let dims = self.shape.len();
+                if axes.len() != dims || (0..dims).any(|d| !axes.contains(&d)) {
+                    panic!("permute: axes are not a permutation of the dimensions");
+                }
+                let shape: Vec<usize> = axes.iter().map(|&a| self.shape[a]).collect();
+                let strides: Vec<usize> = axes.iter().map(|&a| self.sub_size[a] / self.shape[a]).collect();
+                let mut res = Self::zeros(&shape);
+                let mut index = vec![0; dims];
+                for i in 0..res.data.len() {
+                    let from: usize = index.iter().zip(strides.iter()).map(|(i, s)| i * s).sum();
+                    res.data[i] = self.data[from];
+                    // next index of the result in row-major order
+                    for d in (0..dims).rev() {
+                        index[d] += 1;
+                        if index[d] < shape[d] {
+                            break;
+                        }
+                        index[d] = 0;
+                    }
+                }
+                res
+            }
+
+            /* random order of 0..n */
+            pub fn permutation<R: Rng + ?Sized>(n: usize, rng: &mut R) -> Vec<usize> {
+                let mut p: Vec<usize> = (0..n).collect();
+                p.shuffle(rng);
+                p
+            }
+
+            /* reorder the sub arrays along the first dimension */
+            pub fn shuffle_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> &Self {
+                let rows = self.shape[0];
+                let size = self.sub_size[0] / rows;
+                let order = Self::permutation(rows, rng);
+                let data = self.data.clone();
+                for (to, &from) in order.iter().enumerate() {
+                    self.data[to * size..(to + 1) * size].clone_from_slice(&data[from * size..(from + 1) * size]);
                 }
                 self
             }
@@ -234,4 +368,83 @@ macro_rules! new_impl_for_array {
 new_impl_for_array!(f64);
 new_impl_for_array!(i32);
 new_float_impl_for_array!(f64);
-new_int_impl_for_array!(i32);
\ No newline at end of file
+new_int_impl_for_array!(i32);
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::Array;
+
+    const N: usize = 20000;
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(9)
+    }
+
+    fn mean_var(a: &Array<f64>) -> (f64, f64) {
+        let n = a.data.len() as f64;
+        let mean = a.data.iter().sum::<f64>() / n;
+        (mean, a.data.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n)
+    }
+
+    /* mean within 4 standard errors, variance within 5% */
+    fn assert_mean_var(a: &Array<f64>, mean: f64, var: f64) {
+        let (m, v) = mean_var(a);
+        assert!((m - mean).abs() < 4.0 * (var / a.data.len() as f64).sqrt(), "mean {} expected {}", m, mean);
+        assert!((v / var - 1.0).abs() < 0.05, "variance {} expected {}", v, var);
+    }
+
+    #[test]
+    fn uniform() {
+        let a = Array::<f64>::random_with(&[N], -1.0, 3.0, &mut rng());
+        assert!(a.data.iter().all(|&x| (-1.0..3.0).contains(&x)));
+        assert_mean_var(&a, 1.0, 16.0 / 12.0);
+    }
+
+    #[test]
+    fn normal() {
+        let a = Array::<f64>::normal_with(&[N], 2.0, 0.5, &mut rng());
+        assert_mean_var(&a, 2.0, 0.25);
+        // about 95.45% within 2 std
+        let inside = a.data.iter().filter(|&&x| (x - 2.0).abs() < 1.0).count() as f64 / N as f64;
+        assert!((inside - 0.9545).abs() < 0.01, "{}", inside);
+    }
+
+    #[test]
+    fn truncated_normal() {
+        let a = Array::<f64>::truncated_normal_with(&[N], -1.0, 2.0, &mut rng());
+        assert!(a.data.iter().all(|&x| (x + 1.0).abs() <= 4.0));
+        // a standard normal cut at 2 std has variance 0.7737
+        assert_mean_var(&a, -1.0, 4.0 * 0.7737);
+    }
+
+    #[test]
+    fn bernoulli() {
+        let a = Array::<f64>::bernoulli_with(&[N], 0.3, &mut rng());
+        assert!(a.data.iter().all(|&x| x == 0.0 || x == 1.0));
+        assert_mean_var(&a, 0.3, 0.3 * 0.7);
+    }
+
+    #[test]
+    fn categorical() {
+        let a = Array::<f64>::categorical_with(&[N], &[1.0, 0.0, 3.0], &mut rng());
+        let count = |c: f64| a.data.iter().filter(|&&x| x == c).count() as f64 / N as f64;
+        assert_eq!(count(1.0), 0.0);
+        assert!((count(0.0) - 0.25).abs() < 0.01 && (count(2.0) - 0.75).abs() < 0.01, "{} {}", count(0.0), count(2.0));
+    }
+
+    #[test]
+    fn same_seed_same_draws() {
+        assert_eq!(Array::<f64>::normal_with(&[10], 0.0, 1.0, &mut rng()).data, Array::<f64>::normal_with(&[10], 0.0, 1.0, &mut rng()).data);
+    }
+
+    #[test]
+    fn transpose_non_square() {
+        let a = Array::<f64>::with(&[2, 3], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let t = a.t();
+        assert_eq!(&*t.shape, &[3, 2]);
+        assert_eq!(&*t.data, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(&*t.t().data, &*a.data);
+    }
+}